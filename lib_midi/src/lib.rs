@@ -1,9 +1,13 @@
 mod track;
 mod tracks_parser;
 mod midi;
+mod tempo_map;
+mod writer;
 
 pub use {
     track::*,
     tracks_parser::*,
     midi::*,
+    tempo_map::*,
+    writer::*,
 };
@@ -1,5 +1,5 @@
 use {
-    crate::{MidiTrack, TracksParser},
+    crate::{Marker, MidiTrack, TempoMap, TracksParser},
     midly::{Format, Smf, Timing},
     std::fs,
 };
@@ -8,8 +8,13 @@ use {
 pub struct Midi {
     // pub tracks_count: u16,
     pub format: Format,
+    /// Hash of file content, stable between runs
+    pub content_hash: u64,
     pub tracks: Vec<MidiTrack>,
     pub merged_track: MidiTrack,
+    pub tempo_map: TempoMap,
+    /// Markers of all tracks, sorted by time
+    pub markers: Vec<Marker>,
 }
 
 impl Midi {
@@ -64,6 +69,9 @@ impl Midi {
                     let n = n.clone();
                     merged_track.notes.push(n);
                 }
+                merged_track
+                    .channel_events
+                    .extend(trk.channel_events.iter().cloned());
             }
         }
 
@@ -77,6 +85,20 @@ impl Midi {
             .notes
             .sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
 
+        merged_track
+            .channel_events
+            .sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+        let mut markers: Vec<Marker> = tracks
+            .iter()
+            .flat_map(|trk| trk.marker_events.iter())
+            .map(|m| Marker {
+                time: tp.pulses_to_ms(m.time_in_units) / 1000.0,
+                name: m.name.clone(),
+            })
+            .collect();
+        markers.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
         // Asign Unique Id
         for (i, note) in merged_track.notes.iter_mut().enumerate() {
             note.id = i;
@@ -85,8 +107,59 @@ impl Midi {
         Ok(Self {
             // tracks_count: tracks.len() as u16,
             format: smf.header.format,
+            content_hash: content_hash(&data),
             tracks,
             merged_track,
+            tempo_map: tp.tempo_map.clone(),
+            markers,
         })
     }
 }
+
+/// FNV-1a hash
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_recording, RecordedEvent, RecordedMessage};
+
+    #[test]
+    fn content_hash_is_fnv1a() {
+        assert_eq!(content_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(content_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(content_hash(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn content_hash_follows_file_content() {
+        let hash = |key: u8| {
+            let events = [
+                RecordedEvent {
+                    time: 0.0,
+                    ch: 0,
+                    message: RecordedMessage::NoteOn { key, vel: 100 },
+                },
+                RecordedEvent {
+                    time: 1.0,
+                    ch: 0,
+                    message: RecordedMessage::NoteOff { key },
+                },
+            ];
+            parse_recording(&TempoMap::new(480), &events)
+                .unwrap()
+                .content_hash
+        };
+
+        // Every call writes a file of its own, same content under a different name is the same song
+        assert_eq!(hash(60), hash(60));
+        assert_ne!(hash(60), hash(62));
+    }
+}
@@ -0,0 +1,328 @@
+use crate::{TempoEvent, TimeSignatureEvent};
+
+const DEFAULT_TEMPO: u32 = 500_000; // 120 bpm
+
+#[derive(Debug, Clone)]
+pub struct Beat {
+    /// Time in seconds
+    pub time: f32,
+    /// Index of bar that beat belongs to
+    pub bar: usize,
+    /// Index of beat inside of the bar, 0 is a downbeat
+    pub beat: u8,
+}
+
+impl Beat {
+    pub fn is_downbeat(&self) -> bool {
+        self.beat == 0
+    }
+}
+
+/// Converts between midi pulses and song time
+#[derive(Debug, Clone)]
+pub struct TempoMap {
+    u_per_quarter_note: f32,
+    tempo_events: Vec<TempoEvent>,
+    time_signature_events: Vec<TimeSignatureEvent>,
+}
+
+impl TempoMap {
+    pub fn new(u_per_quarter_note: u16) -> Self {
+        Self {
+            u_per_quarter_note: f32::from(u_per_quarter_note),
+            tempo_events: Vec::new(),
+            time_signature_events: Vec::new(),
+        }
+    }
+
+    pub fn set_tempo_events(&mut self, tempo_events: Vec<TempoEvent>) {
+        self.tempo_events = tempo_events;
+    }
+
+    pub fn set_time_signature_events(&mut self, mut events: Vec<TimeSignatureEvent>) {
+        events.sort_by(|a, b| a.time_in_units.partial_cmp(&b.time_in_units).unwrap());
+        self.time_signature_events = events;
+    }
+
+    pub fn u_per_quarter_note(&self) -> f32 {
+        self.u_per_quarter_note
+    }
+
+    pub fn tempo_events(&self) -> &[TempoEvent] {
+        &self.tempo_events
+    }
+
+    pub fn time_signature_events(&self) -> &[TimeSignatureEvent] {
+        &self.time_signature_events
+    }
+
+    fn p_to_ms(&self, time_in_units: f32, tempo: u32) -> f32 {
+        let u_time = tempo as f32 / self.u_per_quarter_note;
+        u_time * time_in_units / 1000.0
+    }
+
+    pub fn pulses_to_ms(&self, event_pulses: f32) -> f32 {
+        let mut res: f32 = 0.0;
+
+        let mut hit = false;
+        let mut last_tempo_event_pulses: f32 = 0.0;
+        let mut running_tempo = DEFAULT_TEMPO;
+
+        for tempo_event in self.tempo_events.iter() {
+            let tempo_event_pulses = tempo_event.time_in_units;
+
+            let delta_pulses = if event_pulses > tempo_event_pulses {
+                tempo_event_pulses - last_tempo_event_pulses
+            } else {
+                hit = true;
+                event_pulses - last_tempo_event_pulses
+            };
+
+            res += self.p_to_ms(delta_pulses, running_tempo);
+
+            if hit {
+                break;
+            }
+
+            running_tempo = tempo_event.tempo;
+            last_tempo_event_pulses = tempo_event_pulses;
+        }
+
+        if !hit {
+            let remaining_pulses = event_pulses - last_tempo_event_pulses;
+            res += self.p_to_ms(remaining_pulses, running_tempo);
+        }
+
+        res
+    }
+
+    pub fn pulses_to_secs(&self, pulses: f32) -> f32 {
+        self.pulses_to_ms(pulses) / 1000.0
+    }
+
+    pub fn secs_to_pulses(&self, secs: f32) -> f32 {
+        let ms = secs * 1000.0;
+
+        let mut last_tempo_event_pulses: f32 = 0.0;
+        let mut last_tempo_event_ms: f32 = 0.0;
+        let mut running_tempo = DEFAULT_TEMPO;
+
+        for tempo_event in self.tempo_events.iter() {
+            let tempo_event_ms = last_tempo_event_ms
+                + self.p_to_ms(
+                    tempo_event.time_in_units - last_tempo_event_pulses,
+                    running_tempo,
+                );
+
+            if ms <= tempo_event_ms {
+                break;
+            }
+
+            running_tempo = tempo_event.tempo;
+            last_tempo_event_pulses = tempo_event.time_in_units;
+            last_tempo_event_ms = tempo_event_ms;
+        }
+
+        let ms_per_pulse = self.p_to_ms(1.0, running_tempo);
+        last_tempo_event_pulses + (ms - last_tempo_event_ms) / ms_per_pulse
+    }
+
+    /// Tempo (in microseconds per quarter note) at given time
+    pub fn tempo_at(&self, secs: f32) -> u32 {
+        let pulses = self.secs_to_pulses(secs);
+
+        self.tempo_events
+            .iter()
+            .take_while(|e| e.time_in_units <= pulses)
+            .last()
+            .map(|e| e.tempo)
+            .unwrap_or(DEFAULT_TEMPO)
+    }
+
+    /// Quarter notes per minute at given time
+    pub fn bpm_at(&self, secs: f32) -> f32 {
+        60_000_000.0 / self.tempo_at(secs) as f32
+    }
+
+    /// Time signature (numerator, denominator) at given time
+    pub fn time_signature_at(&self, secs: f32) -> (u8, u8) {
+        let pulses = self.secs_to_pulses(secs);
+
+        self.time_signature_events
+            .iter()
+            .take_while(|e| e.time_in_units <= pulses)
+            .last()
+            .map(|e| (e.numerator, e.denominator))
+            .unwrap_or((4, 4))
+    }
+
+    /// Every beat from the song start up to `end` (in seconds)
+    pub fn beats(&self, end: f32) -> Vec<Beat> {
+        let end_pulses = self.secs_to_pulses(end);
+
+        let mut beats = Vec::new();
+
+        let mut pulses = 0.0;
+        let mut bar = 0;
+        let mut beat = 0;
+
+        let mut signatures = self.time_signature_events.iter().peekable();
+        let (mut numerator, mut denominator) = (4, 4);
+
+        while pulses <= end_pulses {
+            // Time signature change always starts a new bar
+            while let Some(sig) = signatures.peek() {
+                if sig.time_in_units > pulses {
+                    break;
+                }
+
+                if beat != 0 {
+                    bar += 1;
+                    beat = 0;
+                }
+                numerator = sig.numerator.max(1);
+                denominator = sig.denominator.max(1);
+                signatures.next();
+            }
+
+            beats.push(Beat {
+                time: self.pulses_to_secs(pulses),
+                bar,
+                beat,
+            });
+
+            pulses += self.u_per_quarter_note * 4.0 / f32::from(denominator);
+
+            beat += 1;
+            if beat >= numerator {
+                beat = 0;
+                bar += 1;
+            }
+        }
+
+        beats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tempo(time_in_units: f32, tempo: u32) -> TempoEvent {
+        TempoEvent {
+            time_in_units,
+            tempo,
+        }
+    }
+
+    fn signature(time_in_units: f32, numerator: u8, denominator: u8) -> TimeSignatureEvent {
+        TimeSignatureEvent {
+            time_in_units,
+            numerator,
+            denominator,
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.001, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn secs_to_pulses_default_tempo() {
+        let map = TempoMap::new(480);
+
+        assert_close(map.secs_to_pulses(0.0), 0.0);
+        assert_close(map.secs_to_pulses(0.5), 480.0);
+        assert_close(map.secs_to_pulses(2.0), 1920.0);
+    }
+
+    #[test]
+    fn secs_to_pulses_tempo_change() {
+        let mut map = TempoMap::new(480);
+        // 120 bpm for the first two quarter notes, then 60 bpm
+        map.set_tempo_events(vec![tempo(0.0, 500_000), tempo(960.0, 1_000_000)]);
+
+        assert_close(map.secs_to_pulses(0.5), 480.0);
+        assert_close(map.secs_to_pulses(1.0), 960.0);
+        assert_close(map.secs_to_pulses(2.0), 1440.0);
+        assert_close(map.secs_to_pulses(3.0), 1920.0);
+
+        for &pulses in &[0.0, 240.0, 960.0, 1200.0, 5000.0] {
+            assert_close(map.secs_to_pulses(map.pulses_to_secs(pulses)), pulses);
+        }
+
+        assert_eq!(map.tempo_at(0.9), 500_000);
+        assert_eq!(map.tempo_at(1.5), 1_000_000);
+        assert_close(map.bpm_at(1.5), 60.0);
+    }
+
+    #[test]
+    fn beats_default_signature() {
+        let map = TempoMap::new(480);
+        let beats = map.beats(2.0);
+
+        // 120 bpm, a beat every half a second, both ends included
+        assert_eq!(beats.len(), 5);
+        for (id, beat) in beats.iter().enumerate() {
+            assert_close(beat.time, id as f32 * 0.5);
+            assert_eq!(beat.bar, id / 4);
+            assert_eq!(beat.beat as usize, id % 4);
+        }
+        assert!(beats[4].is_downbeat());
+    }
+
+    #[test]
+    fn beats_time_signature_change() {
+        let mut map = TempoMap::new(480);
+        // One bar of 4/4, then 6/8
+        map.set_time_signature_events(vec![signature(1920.0, 6, 8), signature(0.0, 4, 4)]);
+
+        let beats = map.beats(3.5);
+        let bars: Vec<(usize, u8)> = beats.iter().map(|b| (b.bar, b.beat)).collect();
+
+        assert_eq!(
+            bars,
+            vec![
+                (0, 0),
+                (0, 1),
+                (0, 2),
+                (0, 3),
+                (1, 0),
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (1, 4),
+                (1, 5),
+                (2, 0),
+            ]
+        );
+        // Eighth notes after the change
+        assert_close(beats[5].time - beats[4].time, 0.25);
+        assert_eq!(map.time_signature_at(0.5), (4, 4));
+        assert_eq!(map.time_signature_at(2.5), (6, 8));
+    }
+
+    #[test]
+    fn time_signature_change_mid_bar_starts_new_bar() {
+        let mut map = TempoMap::new(480);
+        map.set_time_signature_events(vec![signature(0.0, 4, 4), signature(960.0, 3, 4)]);
+
+        let beats = map.beats(2.0);
+        let bars: Vec<(usize, u8)> = beats.iter().map(|b| (b.bar, b.beat)).collect();
+
+        assert_eq!(bars, vec![(0, 0), (0, 1), (1, 0), (1, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn beats_follow_tempo_changes() {
+        let mut map = TempoMap::new(480);
+        map.set_tempo_events(vec![tempo(960.0, 1_000_000)]);
+
+        let times: Vec<f32> = map.beats(3.0).iter().map(|b| b.time).collect();
+
+        assert_eq!(times.len(), 5);
+        for (time, expected) in times.iter().zip(&[0.0, 0.5, 1.0, 2.0, 3.0]) {
+            assert_close(*time, *expected);
+        }
+    }
+}
@@ -10,6 +10,46 @@ pub struct TempoEvent {
     pub tempo: u32,
 }
 
+#[derive(Debug, Clone)]
+pub struct TimeSignatureEvent {
+    pub time_in_units: f32,
+    pub numerator: u8,
+    /// Denominator as a note value (4 for quarter note)
+    pub denominator: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct MarkerEvent {
+    pub time_in_units: f32,
+    pub name: String,
+}
+
+/// Named position in the song (verse, chorus...)
+#[derive(Debug, Clone)]
+pub struct Marker {
+    /// Time in seconds
+    pub time: f32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChannelMessage {
+    ControlChange { controller: u8, value: u8 },
+    ProgramChange { program: u8 },
+    /// 14 bit value, 0x2000 is the center
+    PitchBend { value: u16 },
+    ChannelPressure { pressure: u8 },
+}
+
+/// Non note channel message (controllers, programs, pitch bend)
+#[derive(Debug, Clone)]
+pub struct ChannelEvent {
+    pub time: f32,
+    pub ch: u8,
+    pub track_id: usize,
+    pub message: ChannelMessage,
+}
+
 #[derive(Debug, Clone)]
 pub struct MidiNote {
     pub start: f32,
@@ -23,10 +63,14 @@ pub struct MidiNote {
 
 #[derive(Debug, Clone)]
 pub struct MidiTrack {
+    pub name: Option<String>,
     pub tempo: u32,
     pub tempo_events: Vec<TempoEvent>,
     pub has_tempo: bool,
+    pub time_signature_events: Vec<TimeSignatureEvent>,
+    pub marker_events: Vec<MarkerEvent>,
     pub notes: Vec<MidiNote>,
+    pub channel_events: Vec<ChannelEvent>,
     pub track_id: usize,
 }
 
@@ -36,36 +80,65 @@ impl MidiTrack {
 
         let mut has_tempo = false;
         let mut tempo_events = Vec::new();
+        let mut time_signature_events = Vec::new();
+        let mut marker_events = Vec::new();
+        let mut name = None;
 
         let mut time_in_units: f32 = 0.0;
         for event in track.iter() {
             time_in_units += event.delta.as_int() as f32;
 
             if let TrackEventKind::Meta(meta) = &event.kind {
-                if let MetaMessage::Tempo(t) = &meta {
-                    if !has_tempo {
-                        tempo = t.as_int();
-                        has_tempo = true;
+                match &meta {
+                    MetaMessage::Tempo(t) => {
+                        if !has_tempo {
+                            tempo = t.as_int();
+                            has_tempo = true;
+                        }
+                        tempo_events.push(TempoEvent {
+                            time_in_units,
+                            tempo: t.as_int(),
+                        });
+                    }
+                    MetaMessage::TimeSignature(numerator, denominator, _, _) => {
+                        time_signature_events.push(TimeSignatureEvent {
+                            time_in_units,
+                            numerator: *numerator,
+                            denominator: 1u8.checked_shl(u32::from(*denominator)).unwrap_or(4),
+                        });
+                    }
+                    MetaMessage::Marker(n) => {
+                        marker_events.push(MarkerEvent {
+                            time_in_units,
+                            name: String::from_utf8_lossy(n).trim().to_string(),
+                        });
+                    }
+                    MetaMessage::TrackName(n) => {
+                        if name.is_none() {
+                            name = Some(String::from_utf8_lossy(n).trim().to_string());
+                        }
                     }
-                    tempo_events.push(TempoEvent {
-                        time_in_units,
-                        tempo: t.as_int(),
-                    });
+                    _ => {}
                 }
             };
         }
 
         Self {
+            name,
             tempo,
             tempo_events,
             has_tempo,
+            time_signature_events,
+            marker_events,
             track_id,
             notes: Vec::new(),
+            channel_events: Vec::new(),
         }
     }
 
     pub fn extract_notes(&mut self, events: &[TrackEvent], parent_parser: &mut TracksParser) {
         self.notes.clear();
+        self.channel_events.clear();
 
         let mut time_in_units = 0.0;
 
@@ -140,6 +213,47 @@ impl MidiTrack {
 
                         end_note!(k=>key);
                     }
+                    MidiMessage::Controller { controller, value } => {
+                        self.push_channel_event(
+                            parent_parser,
+                            time_in_units,
+                            channel.as_int(),
+                            ChannelMessage::ControlChange {
+                                controller: controller.as_int(),
+                                value: value.as_int(),
+                            },
+                        );
+                    }
+                    MidiMessage::ProgramChange { program } => {
+                        self.push_channel_event(
+                            parent_parser,
+                            time_in_units,
+                            channel.as_int(),
+                            ChannelMessage::ProgramChange {
+                                program: program.as_int(),
+                            },
+                        );
+                    }
+                    MidiMessage::PitchBend { bend } => {
+                        self.push_channel_event(
+                            parent_parser,
+                            time_in_units,
+                            channel.as_int(),
+                            ChannelMessage::PitchBend {
+                                value: bend.0.as_int(),
+                            },
+                        );
+                    }
+                    MidiMessage::ChannelAftertouch { vel } => {
+                        self.push_channel_event(
+                            parent_parser,
+                            time_in_units,
+                            channel.as_int(),
+                            ChannelMessage::ChannelPressure {
+                                pressure: vel.as_int(),
+                            },
+                        );
+                    }
                     _ => {}
                 }
             }
@@ -148,4 +262,19 @@ impl MidiTrack {
         self.notes
             .sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
     }
+
+    fn push_channel_event(
+        &mut self,
+        parent_parser: &TracksParser,
+        time_in_units: f32,
+        ch: u8,
+        message: ChannelMessage,
+    ) {
+        self.channel_events.push(ChannelEvent {
+            time: parent_parser.pulses_to_ms(time_in_units) / 1000.0,
+            ch,
+            track_id: self.track_id,
+            message,
+        });
+    }
 }
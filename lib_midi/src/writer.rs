@@ -0,0 +1,261 @@
+use {
+    crate::TempoMap,
+    midly::{
+        num::{u15, u24, u28, u4, u7},
+        Format, Header, MetaMessage, MidiMessage, Smf, Timing, TrackEvent, TrackEventKind,
+    },
+    std::{
+        collections::HashSet,
+        path::Path,
+        sync::atomic::{AtomicUsize, Ordering},
+    },
+};
+
+#[derive(Debug, Clone, Copy)]
+pub enum RecordedMessage {
+    NoteOn { key: u8, vel: u8 },
+    NoteOff { key: u8 },
+}
+
+/// Note event of a recorded performance
+#[derive(Debug, Clone)]
+pub struct RecordedEvent {
+    /// Time in seconds
+    pub time: f32,
+    pub ch: u8,
+    pub message: RecordedMessage,
+}
+
+/// Writes recorded events as a parallel midi file,
+/// first track holds tempo and time signatures of `tempo_map`, second one the events
+pub fn write_recording(
+    path: &Path,
+    tempo_map: &TempoMap,
+    events: &[RecordedEvent],
+) -> Result<(), String> {
+    let ppq = tempo_map.u_per_quarter_note() as u16;
+    let mut smf = Smf::new(Header::new(
+        Format::Parallel,
+        Timing::Metrical(u15::new(ppq)),
+    ));
+
+    let mut meta_events: Vec<(u32, MetaMessage)> = Vec::new();
+    for e in tempo_map.tempo_events() {
+        meta_events.push((
+            e.time_in_units as u32,
+            MetaMessage::Tempo(u24::new(e.tempo)),
+        ));
+    }
+    for e in tempo_map.time_signature_events() {
+        // Stored as a power of two
+        let denominator = e.denominator.max(1).trailing_zeros() as u8;
+        meta_events.push((
+            e.time_in_units as u32,
+            MetaMessage::TimeSignature(e.numerator, denominator, 24, 8),
+        ));
+    }
+    meta_events.sort_by_key(|(pulses, _)| *pulses);
+
+    smf.tracks.push(into_track(
+        meta_events
+            .into_iter()
+            .map(|(pulses, message)| (pulses, TrackEventKind::Meta(message))),
+    ));
+
+    let mut events: Vec<&RecordedEvent> = events.iter().collect();
+    events.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+    let to_pulses = |time: f32| tempo_map.secs_to_pulses(time.max(0.0)).round() as u32;
+
+    let mut sounding = HashSet::new();
+    let mut note_events = Vec::new();
+    for e in events.iter() {
+        let message = match e.message {
+            RecordedMessage::NoteOn { key, vel } => {
+                sounding.insert((e.ch, key));
+                MidiMessage::NoteOn {
+                    key: u7::new(key),
+                    vel: u7::new(vel),
+                }
+            }
+            RecordedMessage::NoteOff { key } => {
+                sounding.remove(&(e.ch, key));
+                MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                }
+            }
+        };
+
+        note_events.push((
+            to_pulses(e.time),
+            TrackEventKind::Midi {
+                channel: u4::new(e.ch),
+                message,
+            },
+        ));
+    }
+
+    // Release notes that were still held when recording stopped
+    let end = events.last().map(|e| to_pulses(e.time)).unwrap_or(0);
+    for (ch, key) in sounding {
+        note_events.push((
+            end,
+            TrackEventKind::Midi {
+                channel: u4::new(ch),
+                message: MidiMessage::NoteOff {
+                    key: u7::new(key),
+                    vel: u7::new(0),
+                },
+            },
+        ));
+    }
+
+    smf.tracks.push(into_track(note_events.into_iter()));
+
+    smf.save(path).map_err(|err| err.to_string())
+}
+
+/// Writes `events` to a temporary file and parses it back,
+/// used by tests that need a `Midi` of known content
+#[doc(hidden)]
+pub fn parse_recording(
+    tempo_map: &TempoMap,
+    events: &[RecordedEvent],
+) -> Result<crate::Midi, String> {
+    // Tests run in parallel, every call gets its own file
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    let path = std::env::temp_dir().join(format!(
+        "lib_midi-{}-{}.mid",
+        std::process::id(),
+        NEXT_ID.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    write_recording(&path, tempo_map, events)?;
+    let midi = crate::Midi::new(path.to_str().unwrap_or_default());
+    std::fs::remove_file(&path).ok();
+    midi
+}
+
+/// Converts absolute times (in pulses) into deltas and ends the track
+fn into_track<'a>(events: impl Iterator<Item = (u32, TrackEventKind<'a>)>) -> Vec<TrackEvent<'a>> {
+    let mut track = Vec::new();
+    let mut last = 0;
+
+    for (pulses, kind) in events {
+        track.push(TrackEvent {
+            delta: u28::new(pulses.saturating_sub(last)),
+            kind,
+        });
+        last = last.max(pulses);
+    }
+
+    track.push(TrackEvent {
+        delta: u28::new(0),
+        kind: TrackEventKind::Meta(MetaMessage::EndOfTrack),
+    });
+
+    track
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TempoEvent, TimeSignatureEvent};
+
+    fn note_on(time: f32, key: u8) -> RecordedEvent {
+        RecordedEvent {
+            time,
+            ch: 0,
+            message: RecordedMessage::NoteOn { key, vel: 100 },
+        }
+    }
+
+    fn note_off(time: f32, key: u8) -> RecordedEvent {
+        RecordedEvent {
+            time,
+            ch: 0,
+            message: RecordedMessage::NoteOff { key },
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 0.005, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn notes_are_written_in_song_time() {
+        let tempo_map = TempoMap::new(480);
+        // Out of order on purpose
+        let events = [
+            note_off(1.5, 64),
+            note_on(0.5, 60),
+            note_on(1.0, 64),
+            note_off(1.0, 60),
+        ];
+
+        let midi = parse_recording(&tempo_map, &events).unwrap();
+        let mut notes = midi.merged_track.notes.clone();
+        notes.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].note, 60);
+        assert_eq!(notes[0].vel, 100);
+        assert_close(notes[0].start, 0.5);
+        assert_close(notes[0].duration, 0.5);
+        assert_eq!(notes[1].note, 64);
+        assert_close(notes[1].start, 1.0);
+        assert_close(notes[1].duration, 0.5);
+    }
+
+    #[test]
+    fn tempo_map_is_kept() {
+        let mut tempo_map = TempoMap::new(480);
+        tempo_map.set_tempo_events(vec![
+            TempoEvent {
+                time_in_units: 0.0,
+                tempo: 500_000,
+            },
+            TempoEvent {
+                time_in_units: 960.0,
+                tempo: 1_000_000,
+            },
+        ]);
+        tempo_map.set_time_signature_events(vec![TimeSignatureEvent {
+            time_in_units: 0.0,
+            numerator: 3,
+            denominator: 8,
+        }]);
+
+        // Played after the tempo change, at 60 bpm
+        let events = [note_on(2.0, 60), note_off(3.0, 60)];
+
+        let midi = parse_recording(&tempo_map, &events).unwrap();
+
+        assert_eq!(midi.tempo_map.u_per_quarter_note(), 480.0);
+        assert_eq!(midi.tempo_map.time_signature_at(0.0), (3, 8));
+        assert_eq!(midi.tempo_map.tempo_at(0.5), 500_000);
+        assert_eq!(midi.tempo_map.tempo_at(2.5), 1_000_000);
+
+        let note = &midi.merged_track.notes[0];
+        assert_close(note.start, 2.0);
+        assert_close(note.duration, 1.0);
+    }
+
+    #[test]
+    fn held_notes_are_released_at_the_end() {
+        let tempo_map = TempoMap::new(480);
+        let events = [note_on(0.0, 60), note_on(0.5, 62), note_off(1.0, 62)];
+
+        let midi = parse_recording(&tempo_map, &events).unwrap();
+        let held = midi
+            .merged_track
+            .notes
+            .iter()
+            .find(|n| n.note == 60)
+            .unwrap();
+
+        assert_close(held.start, 0.0);
+        assert_close(held.duration, 1.0);
+    }
+}
@@ -30,11 +30,13 @@ impl MainState {
             None
         };
 
+        let config = Config::new();
+
         Self {
             midi_file,
-            output_manager: OutputManager::new(),
+            output_manager: OutputManager::new(&config),
 
-            config: Config::new(),
+            config,
         }
     }
 }
@@ -106,7 +108,10 @@ impl App {
                     self.target.window.winit_window.set_fullscreen(Some(f));
                 }
             }
-            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+            WindowEvent::CloseRequested => {
+                self.target.state.output_manager.panic();
+                *control_flow = ControlFlow::Exit;
+            }
             _ => {}
         }
 
@@ -125,14 +130,35 @@ impl App {
 
                     let to = Box::new(to);
 
+                    self.game_scene.transition_to(to);
+                }
+                scene::menu_scene::Event::Calibrate => {
+                    let to = |target: &mut Target| -> Box<dyn Scene> {
+                        let state = scene::calibration_scene::CalibrationScene::new(target);
+                        Box::new(state)
+                    };
+
+                    let to = Box::new(to);
+
+                    self.game_scene.transition_to(to);
+                }
+                scene::menu_scene::Event::FreePlay => {
+                    let to = |target: &mut Target| -> Box<dyn Scene> {
+                        let state = scene::free_play_scene::FreePlayScene::new(target);
+                        Box::new(state)
+                    };
+
+                    let to = Box::new(to);
+
                     self.game_scene.transition_to(to);
                 }
             },
             SceneEvent::GoBack => match self.game_scene.scene_type() {
                 SceneType::MainMenu => {
+                    self.target.state.output_manager.panic();
                     *control_flow = ControlFlow::Exit;
                 }
-                SceneType::Playing => {
+                SceneType::Playing | SceneType::Calibration | SceneType::FreePlay => {
                     let to = |target: &mut Target| -> Box<dyn Scene> {
                         let state = scene::menu_scene::MenuScene::new(target);
                         Box::new(state)
@@ -144,6 +170,19 @@ impl App {
                 }
                 SceneType::Transition => {}
             },
+            SceneEvent::NextSong => {
+                let to = |target: &mut Target| -> Box<dyn Scene> {
+                    if target.state.load_next_song() {
+                        Box::new(scene::playing_scene::PlayingScene::new(target))
+                    } else {
+                        Box::new(scene::menu_scene::MenuScene::new(target))
+                    }
+                };
+
+                let to = Box::new(to);
+
+                self.game_scene.transition_to(to);
+            }
             _ => {}
         }
     }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::keyboard_range::KeyboardRange;
+
 #[derive(Serialize, Deserialize, Default)]
 pub struct ColorSchema {
     pub base: (u8, u8, u8),
@@ -14,10 +16,73 @@ pub struct Config {
     #[serde(default = "default_playback_offset")]
     pub playback_offset: f32,
 
+    /// Time (in seconds) it takes for a sent note to be heard, measured by calibration
+    #[serde(default)]
+    pub output_latency: f32,
+    /// Time (in seconds) it takes for a pressed key to reach us, measured by calibration
+    #[serde(default)]
+    pub input_latency: f32,
+
     #[serde(default = "default_play_along")]
     #[serde(skip_serializing)]
     pub play_along: bool,
 
+    /// Notes that start within this window (in seconds) are waited for as one chord
+    #[serde(default = "default_wait_mode_chord_window")]
+    pub wait_mode_chord_window: f32,
+
+    /// How early (in seconds) a key can be pressed before the note it satisfies
+    #[serde(default = "default_wait_mode_early_margin")]
+    pub wait_mode_early_margin: f32,
+
+    /// How far (in seconds) from note start a press still counts as hit
+    #[serde(default = "default_score_hit_window")]
+    pub score_hit_window: f32,
+
+    #[serde(default)]
+    pub metronome: bool,
+
+    /// Bars of metronome clicks before playback starts or resumes
+    #[serde(default)]
+    pub count_in_bars: u8,
+
+    /// Send MIDI clock and transport messages when playing to MIDI out
+    #[serde(default)]
+    pub send_midi_clock: bool,
+
+    /// Play notes that are still sounding at the seek target
+    #[serde(default = "default_chase_notes")]
+    pub chase_notes: bool,
+
+    /// Name of MIDI input that user plays on
+    #[serde(default)]
+    pub midi_input: Option<String>,
+
+    /// Name of MIDI input whose clock and transport drive the playback
+    #[serde(default)]
+    pub midi_clock_input: Option<String>,
+
+    #[serde(default = "default_tempo_trainer_start_speed")]
+    pub tempo_trainer_start_speed: f32,
+    #[serde(default = "default_tempo_trainer_step")]
+    pub tempo_trainer_step: f32,
+    #[serde(default = "default_tempo_trainer_target_speed")]
+    pub tempo_trainer_target_speed: f32,
+    /// Accuracy (0.0 ..= 1.0) needed to speed up after a repetition
+    #[serde(default = "default_tempo_trainer_threshold")]
+    pub tempo_trainer_threshold: f32,
+
+    /// Sample rate of the built-in synth, output device default when not set
+    #[serde(default)]
+    pub synth_sample_rate: Option<u32>,
+    /// Frames rendered by the built-in synth per audio callback, output device default when not set
+    #[serde(default)]
+    pub synth_buffer_size: Option<u32>,
+
+    /// Keys shown on the keyboard in the playing scene
+    #[serde(default)]
+    pub keyboard_range: KeyboardRange,
+
     #[serde(default = "default_color_schema")]
     pub color_schema: Vec<ColorSchema>,
 
@@ -43,7 +108,25 @@ impl Config {
         config.unwrap_or_else(|| Self {
             speed_multiplier: default_speed_multiplier(),
             playback_offset: default_playback_offset(),
+            output_latency: 0.0,
+            input_latency: 0.0,
             play_along: default_play_along(),
+            wait_mode_chord_window: default_wait_mode_chord_window(),
+            wait_mode_early_margin: default_wait_mode_early_margin(),
+            score_hit_window: default_score_hit_window(),
+            metronome: false,
+            count_in_bars: 0,
+            send_midi_clock: false,
+            chase_notes: default_chase_notes(),
+            midi_input: None,
+            midi_clock_input: None,
+            tempo_trainer_start_speed: default_tempo_trainer_start_speed(),
+            tempo_trainer_step: default_tempo_trainer_step(),
+            tempo_trainer_target_speed: default_tempo_trainer_target_speed(),
+            tempo_trainer_threshold: default_tempo_trainer_threshold(),
+            synth_sample_rate: None,
+            synth_buffer_size: None,
+            keyboard_range: Default::default(),
             color_schema: default_color_schema(),
             background_color: Default::default(),
         })
@@ -71,6 +154,38 @@ fn default_play_along() -> bool {
     false
 }
 
+fn default_wait_mode_chord_window() -> f32 {
+    0.05
+}
+
+fn default_wait_mode_early_margin() -> f32 {
+    0.2
+}
+
+fn default_score_hit_window() -> f32 {
+    0.15
+}
+
+fn default_chase_notes() -> bool {
+    true
+}
+
+fn default_tempo_trainer_start_speed() -> f32 {
+    0.5
+}
+
+fn default_tempo_trainer_step() -> f32 {
+    0.05
+}
+
+fn default_tempo_trainer_target_speed() -> f32 {
+    1.0
+}
+
+fn default_tempo_trainer_threshold() -> f32 {
+    0.9
+}
+
 fn default_color_schema() -> Vec<ColorSchema> {
     vec![
         ColorSchema {
@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+/// Listing MIDI ports opens a new client, so they are not listed every frame
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DeviceEvent {
+    Added(String),
+    Removed(String),
+}
+
+/// State of the device that is in use
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    Lost(String),
+    Restored(String),
+}
+
+/// Position of the port called `name`, or else of the first one whose name contains it.
+/// Exact match goes first, so "Piano" does not pick "Piano 2" when both are connected
+pub fn find_port(names: &[String], name: &str) -> Option<usize> {
+    names
+        .iter()
+        .position(|n| n == name)
+        .or_else(|| names.iter().position(|n| n.contains(name)))
+}
+
+/// Reports MIDI ports that appeared or disappeared since the last poll
+pub struct DeviceWatcher {
+    known: Vec<String>,
+    last_poll: Instant,
+}
+
+impl DeviceWatcher {
+    pub fn new(known: Vec<String>) -> Self {
+        Self {
+            known,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// `list` is called only when it is time to poll again
+    pub fn poll(&mut self, list: impl FnOnce() -> Vec<String>) -> Vec<DeviceEvent> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return Vec::new();
+        }
+        self.last_poll = Instant::now();
+
+        let current = list();
+
+        let removed = self
+            .known
+            .iter()
+            .filter(|name| !current.contains(name))
+            .map(|name| DeviceEvent::Removed(name.clone()));
+        let added = current
+            .iter()
+            .filter(|name| !self.known.contains(name))
+            .map(|name| DeviceEvent::Added(name.clone()));
+
+        let events = removed.chain(added).collect();
+        self.known = current;
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Pretends the poll interval has passed
+    fn poll_due(watcher: &mut DeviceWatcher) {
+        watcher.last_poll = Instant::now() - POLL_INTERVAL;
+    }
+
+    #[test]
+    fn does_not_list_before_interval() {
+        let mut watcher = DeviceWatcher::new(names(&["Piano"]));
+
+        let events = watcher.poll(|| panic!("listed too early"));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn reports_added_and_removed_ports() {
+        let mut watcher = DeviceWatcher::new(names(&["Piano", "Synth"]));
+
+        poll_due(&mut watcher);
+        let events = watcher.poll(|| names(&["Synth", "Drums"]));
+        assert_eq!(
+            events,
+            vec![
+                DeviceEvent::Removed("Piano".into()),
+                DeviceEvent::Added("Drums".into())
+            ]
+        );
+
+        poll_due(&mut watcher);
+        assert!(watcher.poll(|| names(&["Synth", "Drums"])).is_empty());
+
+        poll_due(&mut watcher);
+        let events = watcher.poll(|| names(&["Piano", "Synth", "Drums"]));
+        assert_eq!(events, vec![DeviceEvent::Added("Piano".into())]);
+    }
+
+    #[test]
+    fn exact_port_name_goes_first() {
+        let ports = names(&["Piano 2", "Piano", "Pad"]);
+
+        assert_eq!(find_port(&ports, "Piano"), Some(1));
+        assert_eq!(find_port(&ports, "Pa"), Some(2));
+        assert_eq!(find_port(&ports, "Piano 2"), Some(0));
+        assert_eq!(find_port(&ports, "Drums"), None);
+    }
+
+    #[test]
+    fn port_name_falls_back_to_substring() {
+        // System adds client numbers to port names
+        let ports = names(&["Pad 20:0", "Piano 24:0"]);
+
+        assert_eq!(find_port(&ports, "Piano"), Some(1));
+        assert_eq!(find_port(&[], "Piano"), None);
+    }
+}
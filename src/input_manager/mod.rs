@@ -0,0 +1,196 @@
+mod virtual_keyboard;
+use virtual_keyboard::VirtualKeyboard;
+
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::device_watcher::{ConnectionEvent, DeviceEvent, DeviceWatcher};
+
+/// Input name of the computer keyboard
+pub const VIRTUAL_KEYBOARD_NAME: &str = "Computer Keyboard";
+
+#[derive(Debug, Clone, Copy)]
+pub enum InputEvent {
+    NoteOn { key: u8, vel: u8 },
+    NoteOff { key: u8 },
+}
+
+/// Names of available MIDI inputs, computer keyboard is always the last one
+pub fn get_inputs() -> Vec<String> {
+    let mut inputs = match midir::MidiInput::new("Neothesia-in-list") {
+        Ok(midi_in) => midi_in
+            .ports()
+            .iter()
+            .filter_map(|p| midi_in.port_name(p).ok())
+            .collect(),
+        Err(err) => {
+            log::error!("{}", err);
+            Vec::new()
+        }
+    };
+
+    inputs.push(VIRTUAL_KEYBOARD_NAME.to_string());
+    inputs
+}
+
+/// Connection to MIDI keyboard of the user
+pub struct InputManager {
+    port_name: String,
+    /// `None` while the device is unplugged
+    midi_in_conn: Option<midir::MidiInputConnection<()>>,
+    /// Set when computer keyboard is used instead of MIDI device
+    virtual_keyboard: Option<VirtualKeyboard>,
+    midi_in_tx: mpsc::Sender<(Instant, InputEvent)>,
+    midi_in_rec: mpsc::Receiver<(Instant, InputEvent)>,
+    watcher: DeviceWatcher,
+    /// Input the user picked while another one stands in for it, used once it is plugged in
+    wanted_port_name: Option<String>,
+}
+
+impl InputManager {
+    /// Connects to input called `port_name`,
+    /// first available input is used when it is not set or not connected
+    pub fn new(port_name: Option<&str>) -> Option<Self> {
+        let inputs = get_inputs();
+
+        // Ports are matched by name, their order changes with every device that is plugged in
+        let selected = port_name.and_then(|name| inputs.iter().find(|i| i.as_str() == name));
+
+        let mut wanted_port_name = None;
+        let port_name = match selected {
+            Some(name) => name.clone(),
+            None => {
+                if let Some(name) = port_name {
+                    log::warn!("MIDI input {} is not available", name);
+                    wanted_port_name = Some(name.to_string());
+                }
+
+                let name = inputs.first()?.clone();
+                log::info!("Choosing input port: {}", name);
+                name
+            }
+        };
+
+        let (midi_in_tx, midi_in_rec) = mpsc::channel();
+
+        let (midi_in_conn, virtual_keyboard) = if port_name == VIRTUAL_KEYBOARD_NAME {
+            (None, Some(VirtualKeyboard::new(midi_in_tx.clone())))
+        } else {
+            (Some(connect(&port_name, midi_in_tx.clone())?), None)
+        };
+
+        Some(Self {
+            port_name,
+            midi_in_conn,
+            virtual_keyboard,
+            midi_in_tx,
+            midi_in_rec,
+            watcher: DeviceWatcher::new(inputs),
+            wanted_port_name,
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Computer keyboard takes letter, digit and punctuation keys over
+    pub fn is_computer_keyboard(&self) -> bool {
+        self.virtual_keyboard.is_some()
+    }
+
+    /// Passes window keyboard events to computer keyboard input,
+    /// returns true if the key was used as a piano key
+    pub fn key_event(
+        &mut self,
+        input: &winit::event::KeyboardInput,
+        modifiers: winit::event::ModifiersState,
+    ) -> bool {
+        match &mut self.virtual_keyboard {
+            Some(keyboard) => keyboard.key_event(input, modifiers),
+            None => false,
+        }
+    }
+
+    /// Feeds event from other source (like on-screen keyboard) into the input
+    pub fn send(&self, event: InputEvent) {
+        self.midi_in_tx.send((Instant::now(), event)).ok();
+    }
+
+    pub fn try_recv(&self) -> Option<(Instant, InputEvent)> {
+        self.midi_in_rec.try_recv().ok()
+    }
+
+    /// Drops the connection when the device gets unplugged and reconnects once it is back
+    pub fn check_devices(&mut self) -> Option<ConnectionEvent> {
+        let mut res = None;
+
+        for event in self.watcher.poll(get_inputs) {
+            match event {
+                DeviceEvent::Removed(name) if name == self.port_name => {
+                    log::warn!("MIDI input {} disconnected", name);
+                    self.midi_in_conn = None;
+                    res = Some(ConnectionEvent::Lost(name));
+                }
+                DeviceEvent::Added(name) if Some(&name) == self.wanted_port_name.as_ref() => {
+                    if let Some(conn) = connect(&name, self.midi_in_tx.clone()) {
+                        log::info!("Switching to MIDI input {}", name);
+                        self.midi_in_conn = Some(conn);
+                        self.virtual_keyboard = None;
+                        self.port_name = name.clone();
+                        self.wanted_port_name = None;
+                        res = Some(ConnectionEvent::Restored(name));
+                    }
+                }
+                DeviceEvent::Added(name)
+                    if name == self.port_name && self.midi_in_conn.is_none() =>
+                {
+                    self.midi_in_conn = connect(&name, self.midi_in_tx.clone());
+                    if self.midi_in_conn.is_some() {
+                        log::info!("MIDI input {} reconnected", name);
+                        res = Some(ConnectionEvent::Restored(name));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        res
+    }
+}
+
+fn connect(
+    port_name: &str,
+    tx: mpsc::Sender<(Instant, InputEvent)>,
+) -> Option<midir::MidiInputConnection<()>> {
+    let midi_in = midir::MidiInput::new("Neothesia-in").ok()?;
+    let in_port = midi_in
+        .ports()
+        .into_iter()
+        .find(|p| midi_in.port_name(p).ok().as_deref() == Some(port_name))?;
+
+    midi_in
+        .connect(
+            &in_port,
+            "neothesia-read-input",
+            move |_, message, _| {
+                // Stamp as soon as possible, it is used to measure latency
+                let stamp = Instant::now();
+
+                if message.len() == 3 {
+                    let key = message[1];
+                    if key >= 21 && key <= 108 {
+                        let status = message[0] & 0xF0;
+                        let vel = message[2];
+                        if status == 0x80 || (status == 0x90 && vel == 0) {
+                            tx.send((stamp, InputEvent::NoteOff { key })).ok();
+                        } else if status == 0x90 {
+                            tx.send((stamp, InputEvent::NoteOn { key, vel })).ok();
+                        }
+                    }
+                }
+            },
+            (),
+        )
+        .ok()
+}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use winit::event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode};
+
+use super::InputEvent;
+
+/// Lowest and highest key of 88 key piano, same range as MIDI input accepts
+const FIRST_KEY: i16 = 21;
+const LAST_KEY: i16 = 108;
+
+const DEFAULT_BASE: i16 = 48;
+const DEFAULT_VELOCITY: u8 = 100;
+const VELOCITY_STEP: u8 = 16;
+
+/// Offset (in semitones) from base note of keys in bottom (Z) and top (Q) rows,
+/// letters play white keys and the row above them black ones
+fn key_offset(key: VirtualKeyCode) -> Option<i16> {
+    use VirtualKeyCode::*;
+
+    let offset = match key {
+        Z => 0,
+        S => 1,
+        X => 2,
+        D => 3,
+        C => 4,
+        V => 5,
+        G => 6,
+        B => 7,
+        H => 8,
+        N => 9,
+        J => 10,
+        M => 11,
+        Comma => 12,
+        L => 13,
+        Period => 14,
+        Semicolon => 15,
+        Slash => 16,
+
+        Q => 12,
+        Key2 => 13,
+        W => 14,
+        Key3 => 15,
+        E => 16,
+        R => 17,
+        Key5 => 18,
+        T => 19,
+        Key6 => 20,
+        Y => 21,
+        Key7 => 22,
+        U => 23,
+        I => 24,
+        Key9 => 25,
+        O => 26,
+        Key0 => 27,
+        P => 28,
+        _ => return None,
+    };
+
+    Some(offset)
+}
+
+/// Computer keyboard that plays like a MIDI keyboard.
+/// F1/F2 shift octave down/up, F3/F4 lower/raise velocity.
+/// Keys pressed with Ctrl or Alt are left to hotkeys
+pub struct VirtualKeyboard {
+    tx: mpsc::Sender<(Instant, InputEvent)>,
+    /// Note played by Z key
+    base: i16,
+    velocity: u8,
+    /// Held keys with the notes they started, so octave shift does not leave notes hanging
+    pressed: HashMap<VirtualKeyCode, u8>,
+}
+
+impl VirtualKeyboard {
+    pub fn new(tx: mpsc::Sender<(Instant, InputEvent)>) -> Self {
+        Self {
+            tx,
+            base: DEFAULT_BASE,
+            velocity: DEFAULT_VELOCITY,
+            pressed: HashMap::new(),
+        }
+    }
+
+    /// Returns true if the key was used
+    pub fn key_event(&mut self, input: &KeyboardInput, modifiers: ModifiersState) -> bool {
+        let key = match input.virtual_keycode {
+            Some(key) => key,
+            None => return false,
+        };
+        let pressed = input.state == ElementState::Pressed;
+
+        match key {
+            VirtualKeyCode::F1 | VirtualKeyCode::F2 => {
+                if pressed {
+                    let step = if key == VirtualKeyCode::F1 { -12 } else { 12 };
+                    // Whole top row has to stay on the piano
+                    let base = self.base + step;
+                    if base >= FIRST_KEY - 12 && base + 28 <= LAST_KEY {
+                        self.base = base;
+                    }
+                }
+                true
+            }
+            VirtualKeyCode::F3 => {
+                if pressed {
+                    self.velocity = self.velocity.saturating_sub(VELOCITY_STEP).max(1);
+                }
+                true
+            }
+            VirtualKeyCode::F4 => {
+                if pressed {
+                    self.velocity = self.velocity.saturating_add(VELOCITY_STEP).min(127);
+                }
+                true
+            }
+            _ => match key_offset(key) {
+                Some(offset) if pressed => {
+                    if modifiers.ctrl() || modifiers.alt() {
+                        return false;
+                    }
+                    self.press(key, offset);
+                    true
+                }
+                // Release of a key that did not start a note belongs to its hotkey
+                Some(_) => self.release(key),
+                None => false,
+            },
+        }
+    }
+
+    fn press(&mut self, key: VirtualKeyCode, offset: i16) {
+        // Ignore key repeat
+        if self.pressed.contains_key(&key) {
+            return;
+        }
+
+        let note = self.base + offset;
+        if note < FIRST_KEY || note > LAST_KEY {
+            return;
+        }
+        let note = note as u8;
+
+        self.pressed.insert(key, note);
+        self.tx
+            .send((
+                Instant::now(),
+                InputEvent::NoteOn {
+                    key: note,
+                    vel: self.velocity,
+                },
+            ))
+            .ok();
+    }
+
+    /// Returns false if the key was not playing a note
+    fn release(&mut self, key: VirtualKeyCode) -> bool {
+        match self.pressed.remove(&key) {
+            Some(note) => {
+                self.tx
+                    .send((Instant::now(), InputEvent::NoteOff { key: note }))
+                    .ok();
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use serde::{Deserialize, Serialize};
+
+/// Lowest key of 88 key piano (A0)
+const PIANO_FIRST: u8 = 21;
+/// Highest key of 88 key piano (C8)
+const PIANO_LAST: u8 = 108;
+
+/// Keyboard size used by the playing scene
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum KeyboardRange {
+    /// A0 - C8
+    Keys88,
+    /// E1 - G7
+    Keys76,
+    /// C2 - C7
+    Keys61,
+    /// C2 - C6
+    Keys49,
+    /// First and last midi key
+    Custom { first: u8, last: u8 },
+    /// Smallest range (in whole octaves) that fits every note of the song
+    AutoFit,
+}
+
+impl Default for KeyboardRange {
+    fn default() -> Self {
+        KeyboardRange::Custom {
+            first: 36,
+            last: 93,
+        }
+    }
+}
+
+impl KeyboardRange {
+    /// Presets that can be cycled through with a hotkey
+    pub fn next_preset(&self) -> Self {
+        match self {
+            KeyboardRange::Keys88 => KeyboardRange::Keys76,
+            KeyboardRange::Keys76 => KeyboardRange::Keys61,
+            KeyboardRange::Keys61 => KeyboardRange::Keys49,
+            KeyboardRange::Keys49 => KeyboardRange::AutoFit,
+            KeyboardRange::Custom { .. } | KeyboardRange::AutoFit => KeyboardRange::Keys88,
+        }
+    }
+
+    pub fn resolve(&self, midi: Option<&lib_midi::Midi>) -> KeyRange {
+        match *self {
+            KeyboardRange::Keys88 => KeyRange::new(21, 108),
+            KeyboardRange::Keys76 => KeyRange::new(28, 103),
+            KeyboardRange::Keys61 => KeyRange::new(36, 96),
+            KeyboardRange::Keys49 => KeyRange::new(36, 84),
+            KeyboardRange::Custom { first, last } => KeyRange::new(first, last),
+            KeyboardRange::AutoFit => {
+                let notes = midi
+                    .into_iter()
+                    .flat_map(|midi| midi.merged_track.notes.iter())
+                    .filter(|n| n.ch != 9 && n.ch != 8)
+                    .map(|n| n.note);
+
+                let bounds = notes.fold(None, |bounds, note| match bounds {
+                    Some((first, last)) => Some((note.min(first), note.max(last))),
+                    None => Some((note, note)),
+                });
+
+                match bounds {
+                    // From C below the lowest note to C above the highest one
+                    Some((first, last)) => KeyRange::new(first - first % 12, last - last % 12 + 12),
+                    None => KeyRange::new(PIANO_FIRST, PIANO_LAST),
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for KeyboardRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            KeyboardRange::Keys88 => write!(f, "88 keys"),
+            KeyboardRange::Keys76 => write!(f, "76 keys"),
+            KeyboardRange::Keys61 => write!(f, "61 keys"),
+            KeyboardRange::Keys49 => write!(f, "49 keys"),
+            KeyboardRange::Custom { first, last } => write!(f, "Keys {} - {}", first, last),
+            KeyboardRange::AutoFit => write!(f, "Fit to song"),
+        }
+    }
+}
+
+/// Keys (inclusive) that are shown on the keyboard
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyRange {
+    first: u8,
+    last: u8,
+}
+
+impl KeyRange {
+    /// Range is limited to 88 keys piano and always starts and ends with a white key
+    pub fn new(first: u8, last: u8) -> Self {
+        let mut first = first.max(PIANO_FIRST).min(PIANO_LAST);
+        let mut last = last.max(first).min(PIANO_LAST);
+
+        if Self::is_black(first) {
+            first -= 1;
+        }
+        if Self::is_black(last) {
+            last += 1;
+        }
+
+        Self { first, last }
+    }
+
+    pub fn is_black(note: u8) -> bool {
+        matches!(note % 12, 1 | 3 | 6 | 8 | 10)
+    }
+
+    pub fn first(&self) -> u8 {
+        self.first
+    }
+
+    pub fn last(&self) -> u8 {
+        self.last
+    }
+
+    pub fn len(&self) -> usize {
+        (self.last - self.first) as usize + 1
+    }
+
+    pub fn iter(&self) -> std::ops::RangeInclusive<u8> {
+        self.first..=self.last
+    }
+
+    pub fn white_count(&self) -> usize {
+        self.iter().filter(|n| !Self::is_black(*n)).count()
+    }
+
+    pub fn contains(&self, note: u8) -> bool {
+        note >= self.first && note <= self.last
+    }
+
+    /// Index of the key on the keyboard
+    pub fn id(&self, note: u8) -> Option<usize> {
+        if self.contains(note) {
+            Some((note - self.first) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_have_their_size() {
+        let sizes = [
+            (KeyboardRange::Keys88, 88, 52),
+            (KeyboardRange::Keys76, 76, 45),
+            (KeyboardRange::Keys61, 61, 36),
+            (KeyboardRange::Keys49, 49, 29),
+        ];
+
+        for (preset, len, white) in sizes.iter() {
+            let range = preset.resolve(None);
+            assert_eq!(range.len(), *len, "{}", preset);
+            assert_eq!(range.white_count(), *white, "{}", preset);
+        }
+    }
+
+    #[test]
+    fn presets_cycle_back_to_88_keys() {
+        let mut range = KeyboardRange::default();
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            range = range.next_preset();
+            seen.push(range);
+        }
+
+        assert_eq!(
+            seen,
+            vec![
+                KeyboardRange::Keys88,
+                KeyboardRange::Keys76,
+                KeyboardRange::Keys61,
+                KeyboardRange::Keys49,
+                KeyboardRange::AutoFit,
+            ]
+        );
+        assert_eq!(range.next_preset(), KeyboardRange::Keys88);
+    }
+
+    #[test]
+    fn range_starts_and_ends_with_white_key() {
+        // C#2 - F#4
+        let range = KeyRange::new(37, 66);
+        assert_eq!((range.first(), range.last()), (36, 67));
+
+        // Reversed and out of piano range
+        let range = KeyRange::new(120, 10);
+        assert_eq!((range.first(), range.last()), (108, 108));
+
+        let range = KeyRange::new(0, 127);
+        assert_eq!((range.first(), range.last()), (PIANO_FIRST, PIANO_LAST));
+    }
+
+    #[test]
+    fn key_ids() {
+        let range = KeyRange::new(36, 96);
+
+        assert!(!range.contains(35));
+        assert!(range.contains(36));
+        assert!(range.contains(96));
+        assert!(!range.contains(97));
+
+        assert_eq!(range.id(35), None);
+        assert_eq!(range.id(36), Some(0));
+        assert_eq!(range.id(96), Some(60));
+        assert_eq!(range.iter().count(), range.len());
+    }
+
+    #[test]
+    fn auto_fit_rounds_to_octaves() {
+        let events: Vec<lib_midi::RecordedEvent> = [50, 70]
+            .iter()
+            .flat_map(|&key| {
+                vec![
+                    lib_midi::RecordedEvent {
+                        time: 0.0,
+                        ch: 0,
+                        message: lib_midi::RecordedMessage::NoteOn { key, vel: 100 },
+                    },
+                    lib_midi::RecordedEvent {
+                        time: 1.0,
+                        ch: 0,
+                        message: lib_midi::RecordedMessage::NoteOff { key },
+                    },
+                ]
+            })
+            .collect();
+        let midi = lib_midi::parse_recording(&lib_midi::TempoMap::new(480), &events).unwrap();
+
+        let range = KeyboardRange::AutoFit.resolve(Some(&midi));
+        assert_eq!((range.first(), range.last()), (48, 72));
+
+        let range = KeyboardRange::AutoFit.resolve(None);
+        assert_eq!((range.first(), range.last()), (PIANO_FIRST, PIANO_LAST));
+    }
+}
@@ -12,11 +12,19 @@ mod time_manager;
 mod output_manager;
 pub use output_manager::OutputManager;
 
+mod input_manager;
+
+mod device_watcher;
+
+mod midi_clock;
+
 mod transform_uniform;
 use transform_uniform::TransformUniform;
 
 mod config;
 
+mod keyboard_range;
+
 mod rectangle_pipeline;
 
 mod resources;
@@ -25,6 +33,19 @@ mod target;
 
 mod main_state;
 
+mod track_mixer;
+
+mod playlist;
+
+mod scoring;
+
+mod practice_history;
+
+mod performance_recorder;
+
+#[cfg(feature = "synth")]
+mod wav_render;
+
 #[cfg(not(feature = "record"))]
 mod app;
 
@@ -99,6 +120,13 @@ fn run_app() {
 }
 
 fn main() {
+    #[cfg(feature = "synth")]
+    {
+        if let Some(code) = wav_render::run_from_args() {
+            std::process::exit(code);
+        }
+    }
+
     #[cfg(not(feature = "record"))]
     run_app();
     #[cfg(feature = "record")]
@@ -212,6 +240,22 @@ fn run_recorder() {
             n += 1;
         }
     }
+
+    // Encoder is dropped by now, so the video file is complete
+    if let Some(audio) = recorder.finish_audio() {
+        let video = std::path::Path::new("./out/video.mp4");
+        match recorder::mux_audio(video, &audio) {
+            Ok(()) => println!("Muxed {} into {}", audio.display(), video.display()),
+            Err(recorder::MuxError::FfmpegMissing) => println!(
+                "ffmpeg was not found, {} has no sound, audio is saved in {}",
+                video.display(),
+                audio.display()
+            ),
+            Err(recorder::MuxError::Failed(err)) => {
+                log::error!("{}, audio is left in {}", err, audio.display())
+            }
+        }
+    }
 }
 
 pub fn block_on<F>(f: F) -> <F as Future>::Output
@@ -1,8 +1,20 @@
-use crate::{config::Config, output_manager::OutputManager};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::Config, output_manager::OutputManager, playlist::Playlist,
+    practice_history::PracticeHistory, scoring::ScoreReport, track_mixer::TrackMixer,
+};
 
 pub struct MainState {
     pub midi_file: Option<lib_midi::Midi>,
+    pub midi_file_name: Option<String>,
     pub output_manager: OutputManager,
+    pub track_mixer: TrackMixer,
+    /// Songs queued after the current one
+    pub playlist: Playlist,
+    /// Score of the last play along session
+    pub last_score: Option<ScoreReport>,
+    pub practice_history: PracticeHistory,
 
     pub config: Config,
 }
@@ -11,21 +23,81 @@ impl MainState {
     pub fn new() -> Self {
         let args: Vec<String> = std::env::args().collect();
 
-        let midi_file = if args.len() > 1 {
-            if let Ok(midi) = lib_midi::Midi::new(&args[1]) {
-                Some(midi)
-            } else {
-                None
+        let config = Config::new();
+
+        let mut state = Self {
+            midi_file: None,
+            midi_file_name: None,
+            output_manager: OutputManager::new(&config),
+            track_mixer: TrackMixer::default(),
+            playlist: Playlist::default(),
+            last_score: None,
+            practice_history: PracticeHistory::load(),
+
+            config,
+        };
+
+        if args.len() > 2 {
+            let songs = args[1..].iter().map(PathBuf::from).collect();
+            state.set_playlist(Playlist::new(songs)).ok();
+        } else if args.len() > 1 {
+            state.load_midi_file(Path::new(&args[1])).ok();
+        }
+
+        state
+    }
+
+    /// Replaces current midi file, on error no file is selected
+    pub fn load_midi_file(&mut self, path: &Path) -> Result<(), String> {
+        let midi = lib_midi::Midi::new(path.to_str().unwrap_or_default());
+
+        let res = match midi {
+            Ok(midi) => {
+                self.midi_file = Some(midi);
+                self.midi_file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned());
+                Ok(())
+            }
+            Err(err) => {
+                self.midi_file = None;
+                self.midi_file_name = None;
+                Err(err)
             }
-        } else {
-            None
         };
 
-        Self {
-            midi_file,
-            output_manager: OutputManager::new(),
+        self.track_mixer = TrackMixer::new(self.midi_file.as_ref());
+
+        res
+    }
+
+    /// Replaces the playlist and loads its current song,
+    /// song that is already loaded is kept along with its track settings
+    pub fn set_playlist(&mut self, playlist: Playlist) -> Result<(), String> {
+        let loaded = self.midi_file.is_some() && self.playlist.current() == playlist.current();
+        self.playlist = playlist;
+
+        match self.playlist.current().map(Path::to_path_buf) {
+            Some(path) if !loaded => self.load_midi_file(&path),
+            _ => Ok(()),
+        }
+    }
+
+    /// Loads the next song of the playlist, songs that fail to load are skipped.
+    /// Returns false once the playlist is over
+    pub fn load_next_song(&mut self) -> bool {
+        for _ in 0..self.playlist.len() {
+            let path = match self.playlist.advance() {
+                Some(path) => path.to_path_buf(),
+                None => return false,
+            };
 
-            config: Config::new(),
+            match self.load_midi_file(&path) {
+                Ok(()) => return true,
+                Err(err) => log::error!("Skipping {}: {}", path.display(), err),
+            }
         }
+
+        false
     }
 }
@@ -0,0 +1,22 @@
+//! MIDI clock and transport messages, shared by clock output and external clock input
+
+pub const TIMING_CLOCK: u8 = 0xF8;
+pub const START: u8 = 0xFA;
+pub const CONTINUE: u8 = 0xFB;
+pub const STOP: u8 = 0xFC;
+pub const SONG_POSITION_POINTER: u8 = 0xF2;
+
+/// Clock ticks per quarter note
+pub const PPQN: f32 = 24.0;
+/// Song position pointer counts in 16th notes, 6 clock ticks each
+pub const TICKS_PER_SPP: u64 = 6;
+
+/// Playback position in quarter notes since the song start,
+/// times before the start are passed through, they only have to stay negative
+pub fn song_quarters(tempo_map: &lib_midi::TempoMap, time: f32) -> f32 {
+    if time >= 0.0 {
+        tempo_map.secs_to_pulses(time) / tempo_map.u_per_quarter_note()
+    } else {
+        time
+    }
+}
@@ -2,14 +2,21 @@ use crate::output_manager::{OutputConnection, OutputDescriptor};
 
 use midir::{MidiOutput, MidiOutputConnection, MidiOutputPort};
 
+use std::sync::{Arc, Mutex};
+
 pub struct MidiBackend {
     midi_out: MidiOutput,
+    /// Name of the last connected port, shared with panic hook so it can reopen the port
+    last_port_name: Arc<Mutex<Option<String>>>,
 }
 
 impl MidiBackend {
     pub fn new() -> Result<Self, midir::InitError> {
         let midi_out = MidiOutput::new("midi_out")?;
-        Ok(Self { midi_out })
+        Ok(Self {
+            midi_out,
+            last_port_name: Arc::new(Mutex::new(None)),
+        })
     }
 
     pub fn get_outputs(&self) -> Vec<OutputDescriptor> {
@@ -29,15 +36,67 @@ impl MidiBackend {
         outs
     }
 
-    pub fn new_output_connection(port: &MidiPortInfo) -> Option<MidiOutputConnection> {
+    pub fn port_names(&self) -> Vec<String> {
+        let ports = self.midi_out.ports();
+        ports
+            .iter()
+            .filter_map(|p| self.midi_out.port_name(p).ok())
+            .collect()
+    }
+
+    pub fn new_output_connection(&self, port: &MidiPortInfo) -> Option<MidiOutputConnection> {
         let midi_out = MidiOutput::new("midi_out_conn").ok();
 
         if let Some(midi_out) = midi_out {
-            midi_out.connect(&port.port, "out").ok()
+            let conn = midi_out.connect(&port.port, "out").ok();
+            if conn.is_some() {
+                if let Ok(mut last) = self.last_port_name.lock() {
+                    *last = Some(port.name.clone());
+                }
+            }
+            conn
         } else {
             None
         }
     }
+
+    /// Makes sure external synths are not left with stuck notes when we crash
+    pub fn install_panic_hook(&self) {
+        let last_port_name = self.last_port_name.clone();
+
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let name = match last_port_name.try_lock() {
+                Ok(name) => name.clone(),
+                Err(_) => None,
+            };
+
+            if let Some(name) = name {
+                Self::panic_port(&name);
+            }
+            default_hook(info);
+        }));
+    }
+
+    /// Opens a new connection to the port and silences it,
+    /// used when the app crashed and the original connection can't be trusted
+    fn panic_port(name: &str) {
+        let midi_out = match MidiOutput::new("midi_out_panic") {
+            Ok(midi_out) => midi_out,
+            Err(_) => return,
+        };
+
+        let port = midi_out
+            .ports()
+            .into_iter()
+            .find(|p| midi_out.port_name(p).ok().as_deref() == Some(name));
+
+        if let Some(port) = port {
+            if let Ok(mut conn) = midi_out.connect(&port, "panic") {
+                super::send_panic(&mut conn);
+            }
+        }
+    }
 }
 
 impl OutputConnection for MidiOutputConnection {
@@ -51,6 +110,31 @@ impl OutputConnection for MidiOutputConnection {
             self.send(&[0x80 | ch, key, 0]).ok();
         }
     }
+    fn control_change(&mut self, ch: u8, controller: u8, value: u8) {
+        if ch <= 15 {
+            self.send(&[0xB0 | ch, controller & 0x7F, value & 0x7F]).ok();
+        }
+    }
+    fn program_change(&mut self, ch: u8, program: u8) {
+        if ch <= 15 {
+            self.send(&[0xC0 | ch, program & 0x7F]).ok();
+        }
+    }
+    fn pitch_bend(&mut self, ch: u8, value: u16) {
+        if ch <= 15 {
+            let lsb = (value & 0x7F) as u8;
+            let msb = ((value >> 7) & 0x7F) as u8;
+            self.send(&[0xE0 | ch, lsb, msb]).ok();
+        }
+    }
+    fn channel_pressure(&mut self, ch: u8, pressure: u8) {
+        if ch <= 15 {
+            self.send(&[0xD0 | ch, pressure & 0x7F]).ok();
+        }
+    }
+    fn send_raw(&mut self, message: &[u8]) {
+        self.send(message).ok();
+    }
 }
 
 #[derive(Clone)]
@@ -60,6 +144,12 @@ pub struct MidiPortInfo {
     name: String,
 }
 
+impl MidiPortInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
 impl PartialEq for MidiPortInfo {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id && self.name == other.name
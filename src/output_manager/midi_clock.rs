@@ -0,0 +1,210 @@
+use crate::{
+    midi_clock::{CONTINUE, PPQN, SONG_POSITION_POINTER, START, STOP, TICKS_PER_SPP, TIMING_CLOCK},
+    output_manager::OutputConnection,
+};
+
+/// Sends MIDI clock and transport messages that follow playback
+#[derive(Default)]
+pub struct MidiClock {
+    running: bool,
+    /// Clock ticks sent since the song start
+    sent_ticks: u64,
+}
+
+impl MidiClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `quarters` is playback position in quarter notes since the song start
+    pub fn update(&mut self, output: &mut dyn OutputConnection, playing: bool, quarters: f32) {
+        // There is no way to tell a position before the song start
+        let playing = playing && quarters >= 0.0;
+
+        if playing && !self.running {
+            let position = (quarters * 4.0).floor() as u64;
+
+            if position == 0 {
+                output.send_raw(&[START]);
+            } else {
+                let position = position.min(0x3FFF) as u16;
+                output.send_raw(&[
+                    SONG_POSITION_POINTER,
+                    (position & 0x7F) as u8,
+                    (position >> 7) as u8,
+                ]);
+                output.send_raw(&[CONTINUE]);
+            }
+
+            self.sent_ticks = u64::from(position) * TICKS_PER_SPP;
+            self.running = true;
+        } else if !playing && self.running {
+            self.stop(output);
+        }
+
+        if self.running {
+            let ticks = (quarters * PPQN).floor() as u64;
+            while self.sent_ticks < ticks {
+                output.send_raw(&[TIMING_CLOCK]);
+                self.sent_ticks += 1;
+            }
+        }
+    }
+
+    /// Position pointer can only be changed while stopped,
+    /// so we stop and let next update continue from new position
+    pub fn seek(&mut self, output: &mut dyn OutputConnection) {
+        self.stop(output);
+    }
+
+    pub fn stop(&mut self, output: &mut dyn OutputConnection) {
+        if self.running {
+            output.send_raw(&[STOP]);
+            self.running = false;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::midi_clock::song_quarters;
+    use lib_midi::{TempoEvent, TempoMap};
+
+    #[derive(Default)]
+    struct Recorded {
+        messages: Vec<Vec<u8>>,
+    }
+
+    impl OutputConnection for Recorded {
+        fn send_raw(&mut self, message: &[u8]) {
+            self.messages.push(message.to_vec());
+        }
+    }
+
+    impl Recorded {
+        fn ticks(&self) -> usize {
+            self.messages
+                .iter()
+                .filter(|m| m.as_slice() == [TIMING_CLOCK])
+                .count()
+        }
+
+        fn transport(&self) -> Vec<Vec<u8>> {
+            self.messages
+                .iter()
+                .filter(|m| m.as_slice() != [TIMING_CLOCK])
+                .cloned()
+                .collect()
+        }
+    }
+
+    /// 120 bpm for the first two beats, 60 bpm after them
+    fn tempo_map() -> TempoMap {
+        let mut tempo_map = TempoMap::new(480);
+        tempo_map.set_tempo_events(vec![
+            TempoEvent {
+                time_in_units: 0.0,
+                tempo: 500_000,
+            },
+            TempoEvent {
+                time_in_units: 960.0,
+                tempo: 1_000_000,
+            },
+        ]);
+        tempo_map
+    }
+
+    /// Runs the clock from `from` to `to` seconds in 60 fps steps
+    fn play(clock: &mut MidiClock, output: &mut Recorded, from: f32, to: f32) {
+        let tempo_map = tempo_map();
+        let mut time = from;
+        while time < to {
+            clock.update(output, true, song_quarters(&tempo_map, time));
+            time += 1.0 / 60.0;
+        }
+        clock.update(output, true, song_quarters(&tempo_map, to));
+    }
+
+    #[test]
+    fn ticks_follow_tempo_changes() {
+        let mut clock = MidiClock::new();
+        let mut output = Recorded::default();
+
+        // Two beats at 120 bpm
+        play(&mut clock, &mut output, 0.0, 1.0);
+        assert_eq!(output.ticks(), 2 * PPQN as usize);
+
+        // One beat at 60 bpm
+        play(&mut clock, &mut output, 1.0, 2.0);
+        assert_eq!(output.ticks(), 3 * PPQN as usize);
+
+        assert_eq!(output.transport(), vec![vec![START]]);
+    }
+
+    #[test]
+    fn song_position_is_sent_after_seek() {
+        let mut clock = MidiClock::new();
+        let mut output = Recorded::default();
+
+        play(&mut clock, &mut output, 0.0, 0.5);
+        clock.seek(&mut output);
+
+        // 1.5 s is two beats at 120 bpm and a half at 60 bpm, 10 sixteenth notes
+        play(&mut clock, &mut output, 1.5, 1.5);
+        assert_eq!(
+            output.transport(),
+            vec![
+                vec![START],
+                vec![STOP],
+                vec![SONG_POSITION_POINTER, 10, 0],
+                vec![CONTINUE]
+            ]
+        );
+
+        // Ticks go on from the pointer, not from where the clock was before the seek
+        let ticks = output.ticks();
+        play(&mut clock, &mut output, 1.5, 2.0);
+        assert_eq!(output.ticks() - ticks, PPQN as usize / 2);
+    }
+
+    #[test]
+    fn song_position_is_split_into_7_bit_bytes() {
+        let mut clock = MidiClock::new();
+        let mut output = Recorded::default();
+
+        // 100 quarters are 400 (0x190) sixteenth notes
+        clock.update(&mut output, true, 100.0);
+        assert_eq!(
+            output.transport(),
+            vec![vec![SONG_POSITION_POINTER, 0x10, 0x03], vec![CONTINUE]]
+        );
+    }
+
+    #[test]
+    fn frozen_position_sends_nothing() {
+        let mut clock = MidiClock::new();
+        let mut output = Recorded::default();
+
+        play(&mut clock, &mut output, 0.0, 0.5);
+        let sent = output.messages.len();
+
+        // Wait mode holds the song time while the clock keeps running
+        for _ in 0..10 {
+            clock.update(&mut output, true, song_quarters(&tempo_map(), 0.5));
+        }
+        assert_eq!(output.messages.len(), sent);
+
+        clock.update(&mut output, false, song_quarters(&tempo_map(), 0.5));
+        assert_eq!(output.transport().last(), Some(&vec![STOP]));
+    }
+
+    #[test]
+    fn nothing_is_sent_before_song_start() {
+        let mut clock = MidiClock::new();
+        let mut output = Recorded::default();
+
+        clock.update(&mut output, true, song_quarters(&tempo_map(), -1.0));
+        assert!(output.messages.is_empty());
+    }
+}
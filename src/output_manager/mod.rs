@@ -1,17 +1,27 @@
 mod midi_backend;
 use midi_backend::{MidiBackend, MidiPortInfo};
 
+mod midi_clock;
+use midi_clock::MidiClock;
+
 #[cfg(feature = "synth")]
 mod synth_backend;
 
 #[cfg(feature = "synth")]
 use synth_backend::SynthBackend;
+#[cfg(feature = "synth")]
+pub use synth_backend::{MidiEvent, SynthEvent, SynthRenderer};
 
 use std::{
     fmt::{self, Display, Formatter},
     path::PathBuf,
 };
 
+use crate::{
+    config::Config,
+    device_watcher::{ConnectionEvent, DeviceEvent, DeviceWatcher},
+};
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum OutputDescriptor {
     #[cfg(feature = "synth")]
@@ -34,6 +44,30 @@ impl Display for OutputDescriptor {
 pub trait OutputConnection {
     fn note_on(&mut self, _ch: u8, _key: u8, _vel: u8) {}
     fn note_off(&mut self, _ch: u8, _key: u8) {}
+    fn control_change(&mut self, _ch: u8, _controller: u8, _value: u8) {}
+    fn program_change(&mut self, _ch: u8, _program: u8) {}
+    /// 14 bit value, 0x2000 is the center
+    fn pitch_bend(&mut self, _ch: u8, _value: u16) {}
+    fn channel_pressure(&mut self, _ch: u8, _pressure: u8) {}
+    fn send_raw(&mut self, _message: &[u8]) {}
+    /// Metronome click the output renders on its own,
+    /// returns false if it has to be sent as a percussion note instead
+    fn click(&mut self, _accent: bool) -> bool {
+        false
+    }
+}
+
+const ALL_SOUND_OFF: u8 = 120;
+const RESET_ALL_CONTROLLERS: u8 = 121;
+const ALL_NOTES_OFF: u8 = 123;
+
+/// All Notes Off, All Sound Off and Reset All Controllers on every channel
+fn send_panic(conn: &mut dyn OutputConnection) {
+    for ch in 0..16 {
+        conn.control_change(ch, ALL_NOTES_OFF, 0);
+        conn.control_change(ch, ALL_SOUND_OFF, 0);
+        conn.control_change(ch, RESET_ALL_CONTROLLERS, 0);
+    }
 }
 
 struct DummyOutput {}
@@ -45,15 +79,21 @@ pub struct OutputManager {
     midi_backend: Option<MidiBackend>,
 
     output_connection: (OutputDescriptor, Box<dyn OutputConnection>),
+    midi_clock: MidiClock,
+
+    output_watcher: DeviceWatcher,
+    /// Name of MIDI out that got unplugged while in use
+    lost_output: Option<String>,
 
     pub selected_output_id: Option<usize>,
     pub selected_font_path: Option<PathBuf>,
 }
 
 impl OutputManager {
-    pub fn new() -> Self {
+    #[allow(unused_variables)]
+    pub fn new(config: &Config) -> Self {
         #[cfg(feature = "synth")]
-        let synth_backend = match SynthBackend::new() {
+        let synth_backend = match SynthBackend::new(config) {
             Ok(synth_backend) => Some(synth_backend),
             Err(err) => {
                 log::error!("{:?}", err);
@@ -62,19 +102,34 @@ impl OutputManager {
         };
 
         let midi_backend = match MidiBackend::new() {
-            Ok(midi_device_manager) => Some(midi_device_manager),
+            Ok(midi_device_manager) => {
+                midi_device_manager.install_panic_hook();
+                Some(midi_device_manager)
+            }
             Err(e) => {
                 log::error!("{}", e);
                 None
             }
         };
 
+        let output_watcher = DeviceWatcher::new(
+            midi_backend
+                .as_ref()
+                .map(|midi| midi.port_names())
+                .unwrap_or_default(),
+        );
+
         Self {
             #[cfg(feature = "synth")]
             synth_backend,
             midi_backend,
 
             output_connection: (OutputDescriptor::DummyOutput, Box::new(DummyOutput {})),
+            midi_clock: MidiClock::new(),
+
+            output_watcher,
+            lost_output: None,
+
             selected_output_id: None,
             selected_font_path: None,
         }
@@ -98,26 +153,34 @@ impl OutputManager {
 
     pub fn connect(&mut self, desc: OutputDescriptor) {
         if desc != self.output_connection.0 {
+            self.lost_output = None;
+
+            self.stop_midi_clock();
+            self.panic();
+
             match desc {
                 #[cfg(feature = "synth")]
                 OutputDescriptor::Synth(ref font) => {
                     if let Some(ref mut synth) = self.synth_backend {
-                        if let Some(font) = font.clone() {
-                            self.output_connection =
-                                (desc, Box::new(synth.new_output_connection(&font)));
-                            self.selected_font_path = Some(font);
-                        } else {
-                            let path = crate::resources::default_sf2();
-                            if path.exists() {
-                                self.output_connection =
-                                    (desc, Box::new(synth.new_output_connection(&path)));
-                                self.selected_font_path = Some(path);
+                        let font = font.clone().unwrap_or_else(crate::resources::default_sf2);
+
+                        if font.exists() {
+                            match synth.new_output_connection(&font) {
+                                Ok(conn) => {
+                                    self.output_connection = (desc, Box::new(conn));
+                                    self.selected_font_path = Some(font);
+                                }
+                                Err(err) => log::error!("{}", err),
                             }
                         }
                     }
                 }
                 OutputDescriptor::MidiOut(ref info) => {
-                    if let Some(conn) = MidiBackend::new_output_connection(info) {
+                    let conn = self
+                        .midi_backend
+                        .as_ref()
+                        .and_then(|midi| midi.new_output_connection(info));
+                    if let Some(conn) = conn {
                         self.output_connection = (desc, Box::new(conn));
                     }
                 }
@@ -128,6 +191,65 @@ impl OutputManager {
         }
     }
 
+    /// Events are received by the caller and rendered with `SynthRenderer`, used when recording video
+    #[cfg(feature = "synth")]
+    pub fn connect_offline(&mut self) -> std::sync::mpsc::Receiver<SynthEvent> {
+        self.stop_midi_clock();
+        self.panic();
+
+        let (conn, rx) = synth_backend::offline_connection();
+        self.output_connection = (OutputDescriptor::Synth(None), Box::new(conn));
+
+        rx
+    }
+
+    /// Drops MIDI out connection when the device gets unplugged
+    /// and reconnects once device with the same name is back
+    pub fn check_devices(&mut self) -> Option<ConnectionEvent> {
+        let midi_backend = match &self.midi_backend {
+            Some(midi) => midi,
+            None => return None,
+        };
+
+        let events = self.output_watcher.poll(|| midi_backend.port_names());
+
+        let mut res = None;
+        for event in events {
+            match event {
+                DeviceEvent::Removed(name) => {
+                    let is_active = match &self.output_connection.0 {
+                        OutputDescriptor::MidiOut(info) => info.name() == name,
+                        _ => false,
+                    };
+
+                    if is_active {
+                        log::warn!("MIDI output {} disconnected", name);
+                        self.midi_clock = MidiClock::new();
+                        self.output_connection =
+                            (OutputDescriptor::DummyOutput, Box::new(DummyOutput {}));
+                        self.lost_output = Some(name.clone());
+                        res = Some(ConnectionEvent::Lost(name));
+                    }
+                }
+                DeviceEvent::Added(name) if self.lost_output.as_ref() == Some(&name) => {
+                    let desc = self.get_outputs().into_iter().find(|out| match out {
+                        OutputDescriptor::MidiOut(info) => info.name() == name,
+                        _ => false,
+                    });
+
+                    if let Some(desc) = desc {
+                        self.connect(desc);
+                        log::info!("MIDI output {} reconnected", name);
+                        res = Some(ConnectionEvent::Restored(name));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        res
+    }
+
     pub fn note_on(&mut self, ch: u8, key: u8, vel: u8) {
         self.output_connection.1.note_on(ch, key, vel);
     }
@@ -135,4 +257,74 @@ impl OutputManager {
     pub fn note_off(&mut self, ch: u8, key: u8) {
         self.output_connection.1.note_off(ch, key);
     }
+
+    pub fn control_change(&mut self, ch: u8, controller: u8, value: u8) {
+        self.output_connection
+            .1
+            .control_change(ch, controller, value);
+    }
+
+    pub fn program_change(&mut self, ch: u8, program: u8) {
+        self.output_connection.1.program_change(ch, program);
+    }
+
+    pub fn pitch_bend(&mut self, ch: u8, value: u16) {
+        self.output_connection.1.pitch_bend(ch, value);
+    }
+
+    pub fn channel_pressure(&mut self, ch: u8, pressure: u8) {
+        self.output_connection.1.channel_pressure(ch, pressure);
+    }
+
+    pub fn send_raw(&mut self, message: &[u8]) {
+        self.output_connection.1.send_raw(message);
+    }
+
+    /// Returns false if the output can't click on its own
+    pub fn click(&mut self, accent: bool) -> bool {
+        self.output_connection.1.click(accent)
+    }
+
+    /// Silences every channel of the output
+    pub fn panic(&mut self) {
+        send_panic(&mut *self.output_connection.1);
+    }
+
+    pub fn channel_message(&mut self, ch: u8, message: &lib_midi::ChannelMessage) {
+        use lib_midi::ChannelMessage;
+
+        match *message {
+            ChannelMessage::ControlChange { controller, value } => {
+                self.control_change(ch, controller, value)
+            }
+            ChannelMessage::ProgramChange { program } => self.program_change(ch, program),
+            ChannelMessage::PitchBend { value } => self.pitch_bend(ch, value),
+            ChannelMessage::ChannelPressure { pressure } => self.channel_pressure(ch, pressure),
+        }
+    }
+
+    /// Clock is only sent to MIDI out ports,
+    /// `quarters` is playback position in quarter notes since the song start
+    pub fn update_midi_clock(&mut self, playing: bool, quarters: f32) {
+        if let OutputDescriptor::MidiOut(_) = self.output_connection.0 {
+            self.midi_clock
+                .update(&mut *self.output_connection.1, playing, quarters);
+        }
+    }
+
+    /// Has to be called after every jump in playback position
+    pub fn seek_midi_clock(&mut self) {
+        self.midi_clock.seek(&mut *self.output_connection.1);
+    }
+
+    pub fn stop_midi_clock(&mut self) {
+        self.midi_clock.stop(&mut *self.output_connection.1);
+    }
+}
+
+impl Drop for OutputManager {
+    fn drop(&mut self) {
+        self.stop_midi_clock();
+        self.panic();
+    }
 }
@@ -1,17 +1,228 @@
 extern crate fluidlite_lib;
 
-use std::{error::Error, path::PathBuf, sync::mpsc::Receiver};
-
-use crate::output_manager::{OutputConnection, OutputDescriptor};
+use std::{
+    collections::VecDeque,
+    error::Error,
+    path::Path,
+    sync::mpsc::{Receiver, Sender},
+};
+
+use crate::{
+    config::Config,
+    output_manager::{OutputConnection, OutputDescriptor},
+};
 
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use fluidlite::{IsSettings, Settings};
 
-const SAMPLES_SIZE: usize = 1410;
-
-enum MidiEvent {
+/// Synth events, metronome click is rendered next to the synth instead of on a MIDI channel
+pub enum MidiEvent {
     NoteOn { ch: u8, key: u8, vel: u8 },
     NoteOff { ch: u8, key: u8 },
+    ControlChange { ch: u8, controller: u8, value: u8 },
+    ProgramChange { ch: u8, program: u8 },
+    PitchBend { ch: u8, value: u16 },
+    ChannelPressure { ch: u8, pressure: u8 },
+    Click { accent: bool },
+}
+
+impl MidiEvent {
+    pub fn channel_message(ch: u8, message: &lib_midi::ChannelMessage) -> Self {
+        use lib_midi::ChannelMessage;
+
+        match *message {
+            ChannelMessage::ControlChange { controller, value } => MidiEvent::ControlChange {
+                ch,
+                controller,
+                value,
+            },
+            ChannelMessage::ProgramChange { program } => MidiEvent::ProgramChange { ch, program },
+            ChannelMessage::PitchBend { value } => MidiEvent::PitchBend { ch, value },
+            ChannelMessage::ChannelPressure { pressure } => {
+                MidiEvent::ChannelPressure { ch, pressure }
+            }
+        }
+    }
+}
+
+/// Event scheduled at a sample frame
+pub struct SynthEvent {
+    /// Frame counted from the start of rendering, `None` applies the event at the start of the next block
+    pub frame: Option<u64>,
+    pub event: MidiEvent,
+}
+
+/// Length of metronome click in seconds
+const CLICK_LENGTH: f32 = 0.03;
+
+/// Short sine beep that fades out, so it can't be confused with the drums of the song
+struct Click {
+    freq: f32,
+    gain: f32,
+    /// Frames played so far
+    frame: u32,
+}
+
+impl Click {
+    fn new(accent: bool) -> Self {
+        let (freq, gain) = if accent {
+            (1760.0, 0.5)
+        } else {
+            (1320.0, 0.35)
+        };
+
+        Self {
+            freq,
+            gain,
+            frame: 0,
+        }
+    }
+
+    /// Next sample, `None` once the click is over
+    fn next_sample(&mut self, sample_rate: f32) -> Option<f32> {
+        let time = self.frame as f32 / sample_rate;
+        if time >= CLICK_LENGTH {
+            return None;
+        }
+        self.frame += 1;
+
+        let fade = 1.0 - time / CLICK_LENGTH;
+        Some((time * self.freq * std::f32::consts::PI * 2.0).sin() * self.gain * fade)
+    }
+}
+
+/// Renders fluidlite synth in blocks, events with a frame split the block so they start exactly on it
+pub struct SynthRenderer {
+    synth: fluidlite::Synth,
+    sample_rate: f32,
+    /// Frames rendered so far
+    frame: u64,
+    /// Scheduled events sorted by frame
+    pending: VecDeque<(u64, MidiEvent)>,
+    /// Interleaved stereo output of the synth
+    stereo: Vec<f32>,
+    /// Metronome click that is still sounding
+    click: Option<Click>,
+}
+
+impl SynthRenderer {
+    pub fn new(font: &Path, sample_rate: u32) -> Result<Self, Box<dyn Error>> {
+        let settings = Settings::new()?;
+
+        if let Some(rate) = settings.pick::<_, f64>("synth.sample-rate") {
+            rate.set(sample_rate as f64);
+        }
+
+        let synth = fluidlite::Synth::new(settings)?;
+        synth.sfload(font, true)?;
+        synth.set_sample_rate(sample_rate as f32);
+        synth.set_gain(1.0);
+
+        Ok(Self {
+            synth,
+            sample_rate: sample_rate as f32,
+            frame: 0,
+            pending: VecDeque::new(),
+            stereo: Vec::new(),
+            click: None,
+        })
+    }
+
+    pub fn queue(&mut self, event: SynthEvent) {
+        match event.frame {
+            Some(frame) if frame > self.frame => {
+                // Events of the same frame keep the order they were sent in
+                let id = self
+                    .pending
+                    .iter()
+                    .position(|(f, _)| *f > frame)
+                    .unwrap_or_else(|| self.pending.len());
+                self.pending.insert(id, (frame, event.event));
+            }
+            _ => self.apply(&event.event),
+        }
+    }
+
+    fn apply(&mut self, event: &MidiEvent) {
+        let synth = &self.synth;
+
+        match *event {
+            MidiEvent::NoteOn { ch, key, vel } => {
+                synth.note_on(ch as u32, key as u32, vel as u32).ok();
+            }
+            MidiEvent::NoteOff { ch, key } => {
+                synth.note_off(ch as u32, key as u32).ok();
+            }
+            MidiEvent::ControlChange {
+                ch,
+                controller,
+                value,
+            } => {
+                synth.cc(ch as u32, controller as u32, value as u32).ok();
+            }
+            MidiEvent::ProgramChange { ch, program } => {
+                synth.program_change(ch as u32, program as u32).ok();
+            }
+            MidiEvent::PitchBend { ch, value } => {
+                synth.pitch_bend(ch as u32, value as u32).ok();
+            }
+            MidiEvent::ChannelPressure { ch, pressure } => {
+                synth.channel_pressure(ch as u32, pressure as u32).ok();
+            }
+            MidiEvent::Click { accent } => {
+                self.click = Some(Click::new(accent));
+            }
+        }
+    }
+
+    /// Fills interleaved `out` with `channels` channels,
+    /// left and right are spread over even and odd channels and mixed down for mono
+    pub fn render(&mut self, out: &mut [f32], channels: usize) {
+        let frames = out.len() / channels;
+        let mut done = 0;
+
+        while done < frames {
+            while let Some((frame, _)) = self.pending.front() {
+                if *frame > self.frame {
+                    break;
+                }
+                if let Some((_, event)) = self.pending.pop_front() {
+                    self.apply(&event);
+                }
+            }
+
+            let len = match self.pending.front() {
+                Some((frame, _)) => ((frame - self.frame) as usize).min(frames - done),
+                None => frames - done,
+            };
+
+            self.write(&mut out[done * channels..(done + len) * channels], channels);
+            done += len;
+            self.frame += len as u64;
+        }
+    }
+
+    fn write(&mut self, out: &mut [f32], channels: usize) {
+        self.stereo.resize(out.len() / channels * 2, 0.0);
+        self.synth.write(self.stereo.as_mut_slice()).ok();
+
+        for (frame, lr) in out.chunks_mut(channels).zip(self.stereo.chunks(2)) {
+            if channels == 1 {
+                frame[0] = (lr[0] + lr[1]) / 2.0;
+            } else {
+                for (id, sample) in frame.iter_mut().enumerate() {
+                    *sample = lr[id % 2];
+                }
+            }
+
+            if let Some(click) = &mut self.click {
+                match click.next_sample(self.sample_rate) {
+                    Some(value) => frame.iter_mut().for_each(|sample| *sample += value),
+                    None => self.click = None,
+                }
+            }
+        }
+    }
 }
 
 pub struct SynthBackend {
@@ -19,109 +230,123 @@ pub struct SynthBackend {
     device: cpal::Device,
 
     stream_config: cpal::StreamConfig,
+    /// Config of the device, kept as a fallback while `stream_config` is overridden by the user
+    default_config: Option<cpal::StreamConfig>,
     sample_format: cpal::SampleFormat,
 }
 
 impl SynthBackend {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    /// Sample rate and buffer size default to the ones of the output device
+    pub fn new(config: &Config) -> Result<Self, Box<dyn Error>> {
         let host = cpal::default_host();
 
         let device = host
             .default_output_device()
             .ok_or("failed to find a default output device")?;
 
-        let config = device.default_output_config()?;
-        let sample_format = config.sample_format();
+        let config_range = device.default_output_config()?;
+        let sample_format = config_range.sample_format();
 
-        let stream_config: cpal::StreamConfig = config.into();
+        let device_config: cpal::StreamConfig = config_range.into();
+
+        let mut stream_config = device_config.clone();
+        if let Some(rate) = config.synth_sample_rate {
+            stream_config.sample_rate = cpal::SampleRate(rate);
+        }
+        if let Some(size) = config.synth_buffer_size {
+            stream_config.buffer_size = cpal::BufferSize::Fixed(size);
+        }
+
+        let default_config =
+            if config.synth_sample_rate.is_some() || config.synth_buffer_size.is_some() {
+                Some(device_config)
+            } else {
+                None
+            };
 
         Ok(Self {
             _host: host,
             device,
 
             stream_config,
+            default_config,
             sample_format,
         })
     }
 
-    fn run<T: cpal::Sample>(&self, rx: Receiver<MidiEvent>, path: &PathBuf) -> cpal::Stream {
-        let mut buff: [f32; SAMPLES_SIZE] = [0.0f32; SAMPLES_SIZE];
-
-        let synth = {
-            let sample_rate = self.stream_config.sample_rate.0;
+    fn run<T: cpal::Sample>(
+        &self,
+        stream_config: &cpal::StreamConfig,
+        path: &Path,
+    ) -> Result<(cpal::Stream, Sender<SynthEvent>), Box<dyn Error>> {
+        let (tx, rx) = std::sync::mpsc::channel::<SynthEvent>();
 
-            let settings = Settings::new().unwrap();
+        let mut renderer = SynthRenderer::new(path, stream_config.sample_rate.0)?;
+        let mut block = Vec::new();
 
-            let rate = settings.pick::<_, f64>("synth.sample-rate").unwrap();
-            rate.set((sample_rate / 2) as f64);
-
-            let synth = fluidlite::Synth::new(settings).unwrap();
-            synth.sfload(path, true).unwrap();
-            synth.set_sample_rate(sample_rate as f32);
-            synth.set_gain(1.0);
-
-            synth
-        };
-
-        let mut sample_clock = 0;
+        let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
 
-        let mut next_value = move || {
-            let out = buff[sample_clock];
+        let channels = stream_config.channels as usize;
 
-            sample_clock += 1;
+        let stream = self.device.build_output_stream(
+            stream_config,
+            move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
+                // Whole chord has to start in the same block
+                while let Ok(event) = rx.try_recv() {
+                    renderer.queue(event);
+                }
 
-            if sample_clock == SAMPLES_SIZE {
-                synth.write(buff.as_mut()).unwrap();
-                sample_clock = 0;
-            }
+                block.resize(output.len(), 0.0);
+                renderer.render(&mut block, channels);
 
-            if let Ok(e) = rx.try_recv() {
-                match e {
-                    MidiEvent::NoteOn { ch, key, vel } => {
-                        synth.note_on(ch as u32, key as u32, vel as u32).ok();
-                    }
-                    MidiEvent::NoteOff { ch, key } => {
-                        synth.note_off(ch as u32, key as u32).ok();
-                    }
+                for (sample, value) in output.iter_mut().zip(block.iter()) {
+                    *sample = cpal::Sample::from::<f32>(value);
                 }
-            }
+            },
+            err_fn,
+        )?;
+        stream.play()?;
 
-            out
-        };
+        Ok((stream, tx))
+    }
 
-        let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+    fn start(
+        &self,
+        stream_config: &cpal::StreamConfig,
+        path: &Path,
+    ) -> Result<(cpal::Stream, Sender<SynthEvent>), Box<dyn Error>> {
+        match self.sample_format {
+            cpal::SampleFormat::F32 => self.run::<f32>(stream_config, path),
+            cpal::SampleFormat::I16 => self.run::<i16>(stream_config, path),
+            cpal::SampleFormat::U16 => self.run::<u16>(stream_config, path),
+        }
+    }
 
-        let channels = self.stream_config.channels as usize;
-
-        let stream = self
-            .device
-            .build_output_stream(
-                &self.stream_config,
-                move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
-                    for frame in output.chunks_mut(channels) {
-                        let value: T = cpal::Sample::from::<f32>(&next_value());
-                        for sample in frame.iter_mut() {
-                            *sample = value;
-                        }
-                    }
-                },
-                err_fn,
-            )
-            .unwrap();
-        stream.play().unwrap();
-
-        stream
-    }
-
-    pub fn new_output_connection(&mut self, path: &PathBuf) -> SynthOutputConnection {
-        let (tx, rx) = std::sync::mpsc::channel::<MidiEvent>();
-        let _stream = match self.sample_format {
-            cpal::SampleFormat::F32 => self.run::<f32>(rx, path),
-            cpal::SampleFormat::I16 => self.run::<i16>(rx, path),
-            cpal::SampleFormat::U16 => self.run::<u16>(rx, path),
+    /// Falls back to the device config when it does not support the configured sample rate or buffer size
+    pub fn new_output_connection(
+        &mut self,
+        path: &Path,
+    ) -> Result<SynthOutputConnection, Box<dyn Error>> {
+        let (stream, tx) = match self.start(&self.stream_config, path) {
+            Ok(res) => res,
+            Err(err) => match self.default_config.take() {
+                Some(default_config) => {
+                    log::warn!(
+                        "Could not open synth output with configured sample rate or buffer size ({}), using device defaults",
+                        err
+                    );
+                    let res = self.start(&default_config, path)?;
+                    self.stream_config = default_config;
+                    res
+                }
+                None => return Err(err),
+            },
         };
 
-        SynthOutputConnection { _stream, tx }
+        Ok(SynthOutputConnection {
+            _stream: Some(stream),
+            tx,
+        })
     }
 
     pub fn get_outputs(&self) -> Vec<OutputDescriptor> {
@@ -130,15 +355,68 @@ impl SynthBackend {
 }
 
 pub struct SynthOutputConnection {
-    _stream: cpal::Stream,
-    tx: std::sync::mpsc::Sender<MidiEvent>,
+    /// `None` when the receiver drives its own `SynthRenderer`
+    _stream: Option<cpal::Stream>,
+    tx: std::sync::mpsc::Sender<SynthEvent>,
+}
+
+/// Connection for synth that is rendered by the caller instead of an audio device
+pub fn offline_connection() -> (SynthOutputConnection, Receiver<SynthEvent>) {
+    let (tx, rx) = std::sync::mpsc::channel::<SynthEvent>();
+    (SynthOutputConnection { _stream: None, tx }, rx)
+}
+
+impl SynthOutputConnection {
+    fn send(&self, event: MidiEvent) {
+        self.tx.send(SynthEvent { frame: None, event }).ok();
+    }
 }
 
 impl OutputConnection for SynthOutputConnection {
     fn note_on(&mut self, ch: u8, key: u8, vel: u8) {
-        self.tx.send(MidiEvent::NoteOn { ch, key, vel }).ok();
+        self.send(MidiEvent::NoteOn { ch, key, vel });
     }
     fn note_off(&mut self, ch: u8, key: u8) {
-        self.tx.send(MidiEvent::NoteOff { ch, key }).ok();
+        self.send(MidiEvent::NoteOff { ch, key });
+    }
+    fn control_change(&mut self, ch: u8, controller: u8, value: u8) {
+        self.send(MidiEvent::ControlChange {
+            ch,
+            controller,
+            value,
+        });
+    }
+    fn program_change(&mut self, ch: u8, program: u8) {
+        self.send(MidiEvent::ProgramChange { ch, program });
+    }
+    fn pitch_bend(&mut self, ch: u8, value: u16) {
+        self.send(MidiEvent::PitchBend { ch, value });
+    }
+    fn channel_pressure(&mut self, ch: u8, pressure: u8) {
+        self.send(MidiEvent::ChannelPressure { ch, pressure });
+    }
+    fn click(&mut self, accent: bool) -> bool {
+        self.send(MidiEvent::Click { accent });
+        true
+    }
+    /// Synth understands only channel voice messages, everything else is ignored
+    fn send_raw(&mut self, message: &[u8]) {
+        let status = match message.first() {
+            Some(status) => *status,
+            None => return,
+        };
+        let ch = status & 0x0F;
+        let data = |id: usize| message.get(id).copied().unwrap_or(0) & 0x7F;
+
+        match status & 0xF0 {
+            0x80 => self.note_off(ch, data(1)),
+            0x90 if data(2) == 0 => self.note_off(ch, data(1)),
+            0x90 => self.note_on(ch, data(1), data(2)),
+            0xB0 => self.control_change(ch, data(1), data(2)),
+            0xC0 => self.program_change(ch, data(1)),
+            0xD0 => self.channel_pressure(ch, data(1)),
+            0xE0 => self.pitch_bend(ch, u16::from(data(1)) | u16::from(data(2)) << 7),
+            _ => {}
+        }
     }
 }
@@ -0,0 +1,175 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use lib_midi::{RecordedEvent, RecordedMessage, TempoMap};
+
+/// Channel that recorded notes are written to
+const RECORDING_CH: u8 = 0;
+/// Song time stands still while wait mode waits, notes played meanwhile get at least this length (in seconds)
+const MIN_NOTE_LENGTH: f32 = 0.05;
+
+/// Collects notes played by the user, timed in song seconds
+#[derive(Default)]
+pub struct PerformanceRecorder {
+    events: Vec<RecordedEvent>,
+    /// Start time of every held key
+    held: HashMap<u8, f32>,
+    /// Release time of every key, so the next press of it does not start before it
+    released: HashMap<u8, f32>,
+}
+
+impl PerformanceRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn note_on(&mut self, time: f32, key: u8, vel: u8) {
+        let time = match self.released.get(&key) {
+            Some(released) => time.max(*released),
+            None => time,
+        };
+        self.held.insert(key, time);
+
+        self.events.push(RecordedEvent {
+            time,
+            ch: RECORDING_CH,
+            message: RecordedMessage::NoteOn { key, vel },
+        });
+    }
+
+    pub fn note_off(&mut self, time: f32, key: u8) {
+        let time = match self.held.remove(&key) {
+            Some(start) => time.max(start + MIN_NOTE_LENGTH),
+            None => time,
+        };
+        self.released.insert(key, time);
+
+        self.events.push(RecordedEvent {
+            time,
+            ch: RECORDING_CH,
+            message: RecordedMessage::NoteOff { key },
+        });
+    }
+
+    /// Drops everything played after `time`, so only the latest take of a rewound passage is kept
+    pub fn rewind(&mut self, time: f32) {
+        self.events.retain(|e| e.time < time);
+        self.held.retain(|_, start| *start < time);
+        self.released.retain(|_, released| *released < time);
+    }
+
+    /// Writes the performance to recordings directory,
+    /// file is named after the song and the time of saving
+    pub fn save(&self, tempo_map: &TempoMap, song_name: Option<&str>) -> Option<PathBuf> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let dir = crate::resources::recordings_dir();
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            log::error!("Could not create {}: {}", dir.display(), err);
+            return None;
+        }
+
+        let song_name = song_name
+            .map(|name| name.trim_end_matches(".mid").trim_end_matches(".midi"))
+            .unwrap_or("free-play");
+        let path = dir.join(format!(
+            "{}-{}.mid",
+            song_name,
+            crate::practice_history::unix_time()
+        ));
+
+        match lib_midi::write_recording(&path, tempo_map, &self.events) {
+            Ok(()) => {
+                log::info!("Performance saved to {}", path.display());
+                Some(path)
+            }
+            Err(err) => {
+                log::error!("Could not save performance: {}", err);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn times(recorder: &PerformanceRecorder) -> Vec<f32> {
+        recorder.events.iter().map(|e| e.time).collect()
+    }
+
+    #[test]
+    fn notes_played_while_waiting_are_not_empty() {
+        let mut recorder = PerformanceRecorder::new();
+
+        // Song time is frozen at 1.0 while waiting
+        recorder.note_on(1.0, 60, 100);
+        recorder.note_off(1.0, 60);
+        recorder.note_on(1.0, 60, 100);
+        recorder.note_off(1.0, 60);
+
+        assert_eq!(
+            times(&recorder),
+            vec![
+                1.0,
+                1.0 + MIN_NOTE_LENGTH,
+                1.0 + MIN_NOTE_LENGTH,
+                1.0 + MIN_NOTE_LENGTH + MIN_NOTE_LENGTH
+            ]
+        );
+    }
+
+    #[test]
+    fn longer_notes_keep_their_time() {
+        let mut recorder = PerformanceRecorder::new();
+
+        recorder.note_on(1.0, 60, 100);
+        recorder.note_on(1.0, 64, 100);
+        recorder.note_off(2.0, 60);
+        recorder.note_off(2.5, 64);
+
+        assert_eq!(times(&recorder), vec![1.0, 1.0, 2.0, 2.5]);
+    }
+
+    #[test]
+    fn waited_notes_survive_saving() {
+        let mut recorder = PerformanceRecorder::new();
+
+        recorder.note_on(1.0, 60, 100);
+        recorder.note_off(1.0, 60);
+        recorder.note_on(1.0, 60, 100);
+        recorder.note_off(1.0, 60);
+
+        let midi = lib_midi::parse_recording(&TempoMap::new(480), &recorder.events).unwrap();
+        let notes = &midi.merged_track.notes;
+
+        assert_eq!(notes.len(), 2);
+        for note in notes.iter() {
+            assert_eq!(note.note, 60);
+            assert!(note.duration > MIN_NOTE_LENGTH / 2.0, "{}", note.duration);
+        }
+        assert!(notes[1].start >= notes[0].start + notes[0].duration - 0.005);
+    }
+
+    #[test]
+    fn rewind_drops_later_events() {
+        let mut recorder = PerformanceRecorder::new();
+
+        recorder.note_on(1.0, 60, 100);
+        recorder.note_off(2.0, 60);
+        recorder.note_on(3.0, 62, 100);
+
+        recorder.rewind(1.5);
+        assert_eq!(times(&recorder), vec![1.0]);
+
+        // Release time of the dropped take does not delay new presses
+        recorder.note_on(1.5, 60, 100);
+        assert_eq!(times(&recorder), vec![1.0, 1.5]);
+    }
+}
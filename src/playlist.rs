@@ -0,0 +1,218 @@
+use std::path::{Path, PathBuf};
+
+/// Songs played one after another
+#[derive(Debug, Clone, Default)]
+pub struct Playlist {
+    songs: Vec<PathBuf>,
+    current: usize,
+    /// Start over from the first song after the last one ends
+    pub repeat: bool,
+}
+
+impl Playlist {
+    pub fn new(songs: Vec<PathBuf>) -> Self {
+        Self {
+            songs,
+            current: 0,
+            repeat: false,
+        }
+    }
+
+    /// MIDI files of the folder, sorted by name
+    pub fn from_folder(dir: &Path) -> Result<Self, String> {
+        let entries = std::fs::read_dir(dir).map_err(|err| err.to_string())?;
+
+        let mut songs: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(|ext| ext.eq_ignore_ascii_case("mid") || ext.eq_ignore_ascii_case("midi"))
+                    .unwrap_or(false)
+            })
+            .collect();
+        songs.sort();
+
+        if songs.is_empty() {
+            Err(format!("No MIDI files in {}", dir.display()))
+        } else {
+            Ok(Self::new(songs))
+        }
+    }
+
+    pub fn songs(&self) -> &[PathBuf] {
+        &self.songs
+    }
+
+    pub fn len(&self) -> usize {
+        self.songs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.songs.is_empty()
+    }
+
+    pub fn current_id(&self) -> usize {
+        self.current
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.songs.get(self.current).map(PathBuf::as_path)
+    }
+
+    /// Is there a song to play after the current one
+    pub fn has_next(&self) -> bool {
+        self.current + 1 < self.songs.len() || (self.repeat && !self.songs.is_empty())
+    }
+
+    /// Moves to the next song, returns `None` once the playlist is over
+    pub fn advance(&mut self) -> Option<&Path> {
+        if self.current + 1 < self.songs.len() {
+            self.current += 1;
+        } else if self.repeat && !self.songs.is_empty() {
+            self.current = 0;
+        } else {
+            return None;
+        }
+
+        self.current()
+    }
+
+    /// Swaps the song with the one before (`up`) or after it, current song stays the same
+    pub fn move_song(&mut self, id: usize, up: bool) {
+        let other = if up { id.checked_sub(1) } else { Some(id + 1) };
+
+        if let Some(other) = other {
+            if id < self.songs.len() && other < self.songs.len() {
+                self.songs.swap(id, other);
+
+                if self.current == id {
+                    self.current = other;
+                } else if self.current == other {
+                    self.current = id;
+                }
+            }
+        }
+    }
+
+    /// Puts songs in random order, current song stays the same
+    pub fn shuffle(&mut self) {
+        // Xorshift seeded with the clock, good enough for song order
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        self.shuffle_with_seed(seed);
+    }
+
+    fn shuffle_with_seed(&mut self, seed: u64) {
+        let mut seed = seed | 1;
+        let mut rand = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+
+        // Fisher–Yates
+        for i in (1..self.songs.len()).rev() {
+            let j = (rand() % (i as u64 + 1)) as usize;
+            self.songs.swap(i, j);
+
+            if self.current == i {
+                self.current = j;
+            } else if self.current == j {
+                self.current = i;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playlist(count: usize) -> Playlist {
+        Playlist::new(
+            (0..count)
+                .map(|id| PathBuf::from(format!("{}.mid", id)))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn advance_stops_at_the_end() {
+        let mut list = playlist(2);
+
+        assert_eq!(list.current(), Some(Path::new("0.mid")));
+        assert!(list.has_next());
+        assert_eq!(list.advance(), Some(Path::new("1.mid")));
+        assert!(!list.has_next());
+        assert_eq!(list.advance(), None);
+        assert_eq!(list.current_id(), 1);
+    }
+
+    #[test]
+    fn repeat_starts_over() {
+        let mut list = playlist(2);
+        list.repeat = true;
+
+        list.advance();
+        assert!(list.has_next());
+        assert_eq!(list.advance(), Some(Path::new("0.mid")));
+
+        let mut empty = Playlist::default();
+        empty.repeat = true;
+        assert!(!empty.has_next());
+        assert_eq!(empty.advance(), None);
+    }
+
+    #[test]
+    fn move_song_keeps_current() {
+        let mut list = playlist(4);
+        list.advance();
+
+        // Current song moves along
+        list.move_song(1, false);
+        assert_eq!(list.current(), Some(Path::new("1.mid")));
+        assert_eq!(list.current_id(), 2);
+
+        // Other song moves over the current one
+        list.move_song(3, true);
+        assert_eq!(list.current(), Some(Path::new("1.mid")));
+        assert_eq!(list.current_id(), 3);
+
+        // Unrelated and out of bounds moves
+        list.move_song(0, false);
+        list.move_song(0, true);
+        list.move_song(3, false);
+        assert_eq!(list.current(), Some(Path::new("1.mid")));
+        assert_eq!(
+            list.songs(),
+            &[
+                PathBuf::from("2.mid"),
+                PathBuf::from("0.mid"),
+                PathBuf::from("3.mid"),
+                PathBuf::from("1.mid"),
+            ]
+        );
+    }
+
+    #[test]
+    fn shuffle_keeps_current() {
+        for seed in 0..20 {
+            let mut list = playlist(8);
+            list.advance();
+            list.advance();
+
+            list.shuffle_with_seed(seed);
+
+            assert_eq!(list.current(), Some(Path::new("2.mid")), "seed {}", seed);
+
+            let mut songs = list.songs().to_vec();
+            songs.sort();
+            assert_eq!(songs, playlist(8).songs());
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::scoring::ScoreReport;
+
+const DAY: u64 = 24 * 60 * 60;
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PracticeRecord {
+    /// Content hash of the midi file
+    pub song_hash: u64,
+    pub song_name: String,
+    /// Unix timestamp (in seconds) of the session start
+    pub date: u64,
+    /// Time spent practising (in seconds), pauses excluded
+    pub duration: f32,
+    /// Loop regions (start, end) in song seconds
+    #[serde(default)]
+    pub loop_regions: Vec<(f32, f32)>,
+    pub speed: f32,
+    /// Highest speed reached with tempo trainer
+    #[serde(default)]
+    pub trainer_top_speed: Option<f32>,
+    #[serde(default)]
+    pub score: Option<ScoreReport>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct PracticeHistory {
+    #[serde(default)]
+    pub records: Vec<PracticeRecord>,
+}
+
+impl PracticeHistory {
+    pub fn load() -> Self {
+        let path = crate::resources::practice_history_ron();
+        if let Ok(file) = std::fs::read_to_string(path) {
+            match ron::from_str(&file) {
+                Ok(history) => history,
+                Err(err) => {
+                    log::error!("{:#?}", err);
+                    Self::default()
+                }
+            }
+        } else {
+            Self::default()
+        }
+    }
+
+    fn save(&self) {
+        match ron::ser::to_string_pretty(self, Default::default()) {
+            Ok(s) => {
+                let path = crate::resources::practice_history_ron();
+                if let Err(err) = std::fs::write(path, &s) {
+                    log::error!("{}", err);
+                }
+            }
+            Err(err) => log::error!("{}", err),
+        }
+    }
+
+    pub fn add(&mut self, record: PracticeRecord) {
+        self.records.push(record);
+        self.save();
+    }
+
+    /// Records from the last `days` days
+    fn recent(&self, days: u64) -> impl Iterator<Item = &PracticeRecord> {
+        let since = unix_time().saturating_sub(days * DAY);
+        self.records.iter().filter(move |r| r.date >= since)
+    }
+
+    /// Human readable progress, used by the menu
+    pub fn summary(&self, song_hash: Option<u64>) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        let (sessions, time) = self.recent(7).fold((0, 0.0), |(sessions, time), r| {
+            (sessions + 1, time + r.duration)
+        });
+        lines.push(format!(
+            "Last 7 days: {} min in {} sessions",
+            (time / 60.0).round(),
+            sessions
+        ));
+
+        if let Some(song_hash) = song_hash {
+            let song: Vec<&PracticeRecord> = self
+                .records
+                .iter()
+                .filter(|r| r.song_hash == song_hash)
+                .collect();
+
+            if !song.is_empty() {
+                let time: f32 = song.iter().map(|r| r.duration).sum();
+
+                let mut line = format!(
+                    "This song: {} min in {} sessions",
+                    (time / 60.0).round(),
+                    song.len()
+                );
+
+                let best = song
+                    .iter()
+                    .filter_map(|r| r.score.as_ref())
+                    .map(|s| s.total.grade())
+                    .min();
+                if let Some(best) = best {
+                    line += &format!(", best grade {}", best);
+                }
+
+                let top_speed = song
+                    .iter()
+                    .map(|r| r.trainer_top_speed.unwrap_or(r.speed))
+                    .fold(0.0, f32::max);
+                line += &format!(", top speed {}", (top_speed * 100.0).round() / 100.0);
+
+                lines.push(line);
+            }
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scoring::ScoreStats;
+
+    fn record(song_hash: u64, song_name: &str, date: u64, duration: f32) -> PracticeRecord {
+        PracticeRecord {
+            song_hash,
+            song_name: song_name.into(),
+            date,
+            duration,
+            loop_regions: Vec::new(),
+            speed: 1.0,
+            trainer_top_speed: None,
+            score: None,
+        }
+    }
+
+    fn report(hits: usize, misses: usize) -> ScoreReport {
+        ScoreReport {
+            total: ScoreStats {
+                hits,
+                misses,
+                ..Default::default()
+            },
+            sections: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn last_week_leaves_older_sessions_out() {
+        let now = unix_time();
+        let history = PracticeHistory {
+            records: vec![
+                record(1, "a.mid", now, 600.0),
+                record(2, "b.mid", now - DAY, 300.0),
+                record(1, "a.mid", now - 8 * DAY, 1200.0),
+            ],
+        };
+
+        assert_eq!(
+            history.summary(None),
+            vec!["Last 7 days: 15 min in 2 sessions".to_string()]
+        );
+    }
+
+    #[test]
+    fn song_sessions_are_merged_by_content_hash() {
+        let now = unix_time();
+        let mut renamed = record(1, "renamed.mid", now - 30 * DAY, 120.0);
+        renamed.speed = 0.5;
+        renamed.trainer_top_speed = Some(0.8);
+        renamed.score = Some(report(1, 9));
+
+        let mut latest = record(1, "a.mid", now, 60.0);
+        latest.speed = 0.7;
+        latest.score = Some(report(9, 1));
+
+        let history = PracticeHistory {
+            records: vec![renamed, record(2, "b.mid", now, 600.0), latest],
+        };
+
+        let lines = history.summary(Some(1));
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1],
+            "This song: 3 min in 2 sessions, best grade B, top speed 0.8"
+        );
+
+        // Song that was never practised has no line of its own
+        assert_eq!(history.summary(Some(3)).len(), 1);
+    }
+
+    #[test]
+    fn records_saved_before_newer_fields_still_load() {
+        let history: PracticeHistory = ron::from_str(
+            "(records: [(song_hash: 7, song_name: \"a.mid\", date: 0, duration: 60.0, speed: 1.0)])",
+        )
+        .unwrap();
+
+        let record = &history.records[0];
+        assert!(record.loop_regions.is_empty());
+        assert!(record.trainer_top_speed.is_none());
+        assert!(record.score.is_none());
+    }
+}
@@ -1,24 +1,111 @@
+use std::path::{Path, PathBuf};
+
 use crate::{
+    output_manager::{SynthEvent, SynthRenderer},
     scene::{playing_scene::PlayingScene, Scene},
     target::Target,
+    wav_render::WavWriter,
     wgpu_jumpstart::{Gpu, Window},
 };
 
+/// Video frames per second, same as the time step of the recorder timer
+const FPS: u32 = 60;
+const AUDIO_SAMPLE_RATE: u32 = 48000;
+const AUDIO_CHANNELS: u16 = 2;
+/// 800 at 48kHz, so every video frame has exactly the same amount of audio
+const AUDIO_FRAMES_PER_FRAME: usize = (AUDIO_SAMPLE_RATE / FPS) as usize;
+const AUDIO_PATH: &str = "./out/audio.wav";
+
+/// Synth rendered in step with video frames
+struct AudioRecorder {
+    renderer: SynthRenderer,
+    rx: std::sync::mpsc::Receiver<SynthEvent>,
+    writer: WavWriter,
+    block: Vec<f32>,
+}
+
+impl AudioRecorder {
+    fn new(target: &mut Target) -> Option<Self> {
+        let font = crate::resources::default_sf2();
+        let renderer = match SynthRenderer::new(&font, AUDIO_SAMPLE_RATE) {
+            Ok(renderer) => renderer,
+            Err(err) => {
+                log::error!("Recording without audio, {}: {}", font.display(), err);
+                return None;
+            }
+        };
+
+        std::fs::create_dir("./out").ok();
+        let writer =
+            match WavWriter::create(Path::new(AUDIO_PATH), AUDIO_SAMPLE_RATE, AUDIO_CHANNELS) {
+                Ok(writer) => writer,
+                Err(err) => {
+                    log::error!("Recording without audio, {}: {}", AUDIO_PATH, err);
+                    return None;
+                }
+            };
+
+        let rx = target.state.output_manager.connect_offline();
+
+        Some(Self {
+            renderer,
+            rx,
+            writer,
+            block: vec![0.0; AUDIO_FRAMES_PER_FRAME * AUDIO_CHANNELS as usize],
+        })
+    }
+
+    /// Audio of one video frame, events sent during the frame update start with it.
+    /// Timer steps before frame N is drawn, so it shows time (N + 1) / FPS at N / FPS, same as
+    /// audio block N: a note sounds on the frame it is drawn hitting the keyboard, not before
+    fn render_frame(&mut self) {
+        while let Ok(event) = self.rx.try_recv() {
+            self.renderer.queue(event);
+        }
+
+        self.renderer
+            .render(&mut self.block, AUDIO_CHANNELS as usize);
+        if let Err(err) = self.writer.write(&self.block) {
+            log::error!("{}", err);
+        }
+    }
+}
+
 pub struct Recorder {
     pub target: Target,
 
     pub scene: PlayingScene,
+
+    audio: Option<AudioRecorder>,
+    /// Calibrated (output, input) latencies, put back so recording does not overwrite settings
+    saved_latencies: (f32, f32),
 }
 
 impl Recorder {
     pub fn new(gpu: Gpu, window: Window) -> Self {
         let mut target = Target::new(window, gpu);
 
+        // Offline synth and video frames are in step, calibrated device latencies do not apply
+        let config = &mut target.state.config;
+        let saved_latencies = (config.output_latency, config.input_latency);
+        config.output_latency = 0.0;
+        config.input_latency = 0.0;
+
         // target.resize();
         target.gpu.submit().unwrap();
+
+        // Output has to be connected before the scene sends its first events
+        let audio = AudioRecorder::new(&mut target);
+
         let scene = PlayingScene::new(&mut target);
 
-        Self { target, scene }
+        Self {
+            target,
+            scene,
+
+            audio,
+            saved_latencies,
+        }
     }
 
     pub fn resize(&mut self) {
@@ -30,6 +117,22 @@ impl Recorder {
 
     pub fn update(&mut self) {
         self.scene.update(&mut self.target);
+
+        if let Some(audio) = &mut self.audio {
+            audio.render_frame();
+        }
+    }
+
+    /// Closes the audio file, returns its path if audio was recorded
+    pub fn finish_audio(&mut self) -> Option<PathBuf> {
+        if let Some(audio) = self.audio.take() {
+            match audio.writer.finish() {
+                Ok(()) => return Some(PathBuf::from(AUDIO_PATH)),
+                Err(err) => log::error!("{}", err),
+            }
+        }
+
+        None
     }
 
     pub fn render<'a>(
@@ -73,3 +176,41 @@ impl Recorder {
         }
     }
 }
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let config = &mut self.target.state.config;
+        config.output_latency = self.saved_latencies.0;
+        config.input_latency = self.saved_latencies.1;
+    }
+}
+
+pub enum MuxError {
+    /// `ffmpeg` is not installed, video stays silent
+    FfmpegMissing,
+    Failed(String),
+}
+
+/// Replaces the video with one that has the audio track, needs `ffmpeg` in PATH
+pub fn mux_audio(video: &Path, audio: &Path) -> Result<(), MuxError> {
+    let muxed = video.with_extension("muxed.mp4");
+
+    let status = std::process::Command::new("ffmpeg")
+        .args(&["-y", "-loglevel", "error", "-i"])
+        .arg(video)
+        .arg("-i")
+        .arg(audio)
+        .args(&["-map", "0:v", "-map", "1:a", "-c:v", "copy", "-c:a", "aac"])
+        .arg(&muxed)
+        .status()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => MuxError::FfmpegMissing,
+            _ => MuxError::Failed(format!("Could not run ffmpeg: {}", err)),
+        })?;
+
+    if !status.success() {
+        return Err(MuxError::Failed(format!("ffmpeg failed: {}", status)));
+    }
+
+    std::fs::rename(&muxed, video).map_err(|err| MuxError::Failed(err.to_string()))
+}
@@ -20,6 +20,21 @@ pub fn settings_ron() -> PathBuf {
         .unwrap_or(PathBuf::from("./settings.ron"));
 }
 
+pub fn practice_history_ron() -> PathBuf {
+    #[cfg(not(target_os = "macos"))]
+    return PathBuf::from("./practice_history.ron");
+
+    #[cfg(target_os = "macos")]
+    return bundled_resource_path("practice_history", "ron")
+        .map(PathBuf::from)
+        .unwrap_or(PathBuf::from("./practice_history.ron"));
+}
+
+/// Performances recorded during play along
+pub fn recordings_dir() -> PathBuf {
+    PathBuf::from("./recordings")
+}
+
 #[cfg(target_os = "macos")]
 fn bundled_resource_path(name: &str, extension: &str) -> Option<String> {
     use objc::runtime::{Class, Object};
@@ -0,0 +1,341 @@
+use std::time::{Duration, Instant};
+
+use winit::event::WindowEvent;
+
+use super::{Scene, SceneEvent, SceneType};
+use crate::{
+    input_manager::{InputEvent, InputManager},
+    rectangle_pipeline::{RectangleInstance, RectanglePipeline},
+    target::Target,
+    wgpu_jumpstart::Color,
+};
+
+/// 120 bpm
+const BEAT_INTERVAL: Duration = Duration::from_millis(500);
+/// Beats that let the user catch the pulse, taps are not measured during them
+const LEAD_IN_BEATS: usize = 4;
+const MEASURED_BEATS: usize = 16;
+/// Phase with fewer taps is not measured, so a skipped phase does not overwrite the settings
+const MIN_TAPS: usize = 4;
+
+const FLASH_LENGTH: Duration = Duration::from_millis(100);
+
+/// General MIDI percussion channel (channel 10)
+const PERCUSSION_CH: u8 = 9;
+/// Hi Wood Block
+const CLICK_KEY: u8 = 76;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Silent flashes, taps measure input latency
+    Visual,
+    /// Clicks without flashes, taps measure output + input latency
+    Audio,
+    Done,
+}
+
+pub struct CalibrationScene {
+    input: Option<InputManager>,
+    rectangle_pipeline: RectanglePipeline,
+
+    phase: Phase,
+    phase_start: Instant,
+    /// Index of next beat of the current phase
+    next_beat: usize,
+    last_beat: Option<Instant>,
+    /// Tap offsets (in seconds) from the closest beat
+    offsets: Vec<f32>,
+
+    input_latency: Option<f32>,
+    output_latency: Option<f32>,
+}
+
+impl CalibrationScene {
+    pub fn new(target: &mut Target) -> Self {
+        let input = InputManager::new(target.state.config.midi_input.as_deref());
+        if input.is_none() {
+            log::warn!("No MIDI input available, calibration can't measure taps");
+        }
+
+        Self {
+            input,
+            rectangle_pipeline: RectanglePipeline::new(&target.gpu, &target.transform_uniform),
+
+            phase: Phase::Visual,
+            phase_start: Instant::now(),
+            next_beat: 0,
+            last_beat: None,
+            offsets: Vec::new(),
+
+            input_latency: None,
+            output_latency: None,
+        }
+    }
+
+    fn beat_time(&self, beat: usize) -> Instant {
+        self.phase_start + BEAT_INTERVAL * beat as u32
+    }
+
+    fn tap(&mut self, stamp: Instant) {
+        if self.phase == Phase::Done {
+            return;
+        }
+
+        // Closest beat to the tap
+        let since_start = stamp
+            .saturating_duration_since(self.phase_start)
+            .as_secs_f32();
+        let beat = (since_start / BEAT_INTERVAL.as_secs_f32()).round() as usize;
+
+        if beat < LEAD_IN_BEATS || beat >= LEAD_IN_BEATS + MEASURED_BEATS {
+            return;
+        }
+
+        let beat_time = self.beat_time(beat);
+        let offset = if stamp >= beat_time {
+            (stamp - beat_time).as_secs_f32()
+        } else {
+            -(beat_time - stamp).as_secs_f32()
+        };
+
+        self.offsets.push(offset);
+    }
+
+    /// Median is used so a few missed taps do not spoil the result,
+    /// `None` if there were too few taps to tell
+    fn measured_offset(&mut self) -> Option<f32> {
+        if self.offsets.len() < MIN_TAPS {
+            return None;
+        }
+
+        self.offsets
+            .sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        Some(self.offsets[self.offsets.len() / 2])
+    }
+
+    fn next_phase(&mut self, target: &mut Target) {
+        let offset = self.measured_offset();
+
+        self.phase = match self.phase {
+            Phase::Visual => {
+                self.input_latency = offset.map(|offset| offset.max(0.0));
+                Phase::Audio
+            }
+            Phase::Audio => {
+                match (self.input_latency, offset) {
+                    (Some(input_latency), Some(offset)) => {
+                        let output_latency = (offset - input_latency).max(0.0);
+                        self.output_latency = Some(output_latency);
+
+                        let config = &mut target.state.config;
+                        config.input_latency = input_latency;
+                        config.output_latency = output_latency;
+
+                        log::info!(
+                            "Calibrated input latency: {}ms, output latency: {}ms",
+                            (input_latency * 1000.0).round(),
+                            (output_latency * 1000.0).round()
+                        );
+                    }
+                    _ => {
+                        self.input_latency = None;
+                        log::warn!("Too few taps to calibrate, latency settings are kept");
+                    }
+                }
+
+                Phase::Done
+            }
+            Phase::Done => Phase::Done,
+        };
+
+        self.restart_phase();
+    }
+
+    fn restart_phase(&mut self) {
+        self.phase_start = Instant::now() + BEAT_INTERVAL;
+        self.next_beat = 0;
+        self.last_beat = None;
+        self.offsets.clear();
+    }
+
+    fn description(&self) -> String {
+        let ms = |s: Option<f32>| {
+            s.map(|s| format!("{}ms", (s * 1000.0).round()))
+                .unwrap_or_else(|| "-".into())
+        };
+
+        match self.phase {
+            Phase::Visual => "Tap any key on your MIDI keyboard when the square flashes".into(),
+            Phase::Audio => "Close your eyes and tap any key along with the clicks".into(),
+            Phase::Done if self.output_latency.is_none() => format!(
+                "Too few taps, at least {} per step are needed. Latency settings were kept\nPress Enter to calibrate again, Esc to go back",
+                MIN_TAPS
+            ),
+            Phase::Done => format!(
+                "Input latency: {}  Output latency: {}\nPress Enter to calibrate again, Esc to go back",
+                ms(self.input_latency),
+                ms(self.output_latency)
+            ),
+        }
+    }
+}
+
+impl Scene for CalibrationScene {
+    fn scene_type(&self) -> SceneType {
+        SceneType::Calibration
+    }
+
+    fn start(&mut self) {
+        self.restart_phase();
+    }
+
+    fn done(self: Box<Self>, target: &mut Target) {
+        target
+            .state
+            .output_manager
+            .note_off(PERCUSSION_CH, CLICK_KEY);
+    }
+
+    fn update(&mut self, target: &mut Target) -> SceneEvent {
+        let (window_w, window_h) = {
+            let winit::dpi::LogicalSize { width, height } = target.window.state.logical_size;
+            (width, height)
+        };
+
+        // Connection changes are logged by the managers, calibration only needs them back
+        target.state.output_manager.check_devices();
+        if let Some(input) = &mut self.input {
+            input.check_devices();
+        }
+
+        if let Some(input) = &self.input {
+            let mut taps = Vec::new();
+            while let Some((stamp, event)) = input.try_recv() {
+                if let InputEvent::NoteOn { .. } = event {
+                    taps.push(stamp);
+                }
+            }
+            for stamp in taps {
+                self.tap(stamp);
+            }
+        }
+
+        if self.phase != Phase::Done {
+            let now = Instant::now();
+
+            if now >= self.beat_time(self.next_beat) {
+                if self.phase == Phase::Audio {
+                    let output_manager = &mut target.state.output_manager;
+                    output_manager.note_off(PERCUSSION_CH, CLICK_KEY);
+                    output_manager.note_on(PERCUSSION_CH, CLICK_KEY, 127);
+                }
+
+                self.last_beat = Some(self.beat_time(self.next_beat));
+                self.next_beat += 1;
+            }
+
+            // Give the last beat some time for late taps
+            if self.next_beat > LEAD_IN_BEATS + MEASURED_BEATS {
+                self.next_phase(target);
+            }
+        }
+
+        let flash = self.phase == Phase::Visual
+            && self
+                .last_beat
+                .map(|beat| beat.elapsed() < FLASH_LENGTH)
+                .unwrap_or(false);
+
+        let size = 200.0;
+        let color = if flash {
+            Color::from_rgba8(160, 81, 255, 1.0)
+        } else {
+            Color::from_rgba8(48, 52, 59, 1.0)
+        };
+
+        self.rectangle_pipeline.update_instance_buffer(
+            &mut target.gpu.encoder,
+            &target.gpu.device,
+            vec![RectangleInstance {
+                position: [(window_w - size) / 2.0, (window_h - size) / 2.0],
+                size: [size, size],
+                color: color.into_linear_rgba(),
+            }],
+        );
+
+        let mut lines = vec![self.description()];
+        if self.phase != Phase::Done {
+            let beat = self.next_beat.saturating_sub(LEAD_IN_BEATS);
+            lines.push(format!("{} / {}", beat.min(MEASURED_BEATS), MEASURED_BEATS));
+        }
+        if self.input.is_none() {
+            lines.push("No MIDI input found".into());
+        }
+        let s = lines.join("\n");
+
+        let text = vec![wgpu_glyph::Text::new(&s)
+            .with_color([1.0, 1.0, 1.0, 1.0])
+            .with_scale(30.0)];
+
+        target.text_renderer.queue_text(wgpu_glyph::Section {
+            text,
+            screen_position: (window_w / 2.0, window_h / 2.0 + size),
+            layout: wgpu_glyph::Layout::Wrap {
+                line_breaker: Default::default(),
+                h_align: wgpu_glyph::HorizontalAlign::Center,
+                v_align: wgpu_glyph::VerticalAlign::Top,
+            },
+            ..Default::default()
+        });
+
+        SceneEvent::None
+    }
+
+    fn render(&mut self, target: &mut Target, view: &wgpu::TextureView) {
+        let encoder = &mut target.gpu.encoder;
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            self.rectangle_pipeline
+                .render(&target.transform_uniform, &mut render_pass)
+        }
+    }
+
+    fn window_event(&mut self, target: &mut Target, event: &WindowEvent) -> SceneEvent {
+        if let WindowEvent::KeyboardInput { input, .. } = event {
+            if let Some(manager) = &mut self.input {
+                if manager.key_event(input, target.window.state.modifers_state) {
+                    return SceneEvent::None;
+                }
+            }
+
+            if let winit::event::ElementState::Released = input.state {
+                match input.virtual_keycode {
+                    Some(winit::event::VirtualKeyCode::Escape) => return SceneEvent::GoBack,
+                    Some(winit::event::VirtualKeyCode::Return) => {
+                        if self.phase == Phase::Done {
+                            self.phase = Phase::Visual;
+                            self.input_latency = None;
+                            self.output_latency = None;
+                            self.restart_phase();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        SceneEvent::None
+    }
+}
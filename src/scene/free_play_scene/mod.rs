@@ -0,0 +1,300 @@
+use std::time::Instant;
+
+use winit::event::WindowEvent;
+
+use super::{playing_scene::keyboard::PianoKeyboard, Scene, SceneEvent, SceneType};
+use crate::{
+    device_watcher::ConnectionEvent,
+    input_manager::{InputEvent, InputManager},
+    keyboard_range::KeyRange,
+    performance_recorder::PerformanceRecorder,
+    rectangle_pipeline::{RectangleInstance, RectanglePipeline},
+    target::Target,
+    wgpu_jumpstart::Color,
+};
+
+/// How fast (in logical pixels per second) played notes move away from the keyboard
+const SCROLL_SPEED: f32 = 200.0;
+
+/// Pulses per quarter note of saved free play recordings
+const RECORDING_PPQ: u16 = 480;
+
+/// Note drawn above the keyboard
+struct Bar {
+    key: u8,
+    /// Seconds since scene start
+    start: f32,
+    /// `None` while the key is held
+    end: Option<f32>,
+}
+
+/// Plays notes from MIDI input through the selected output and draws them rising from the keyboard
+pub struct FreePlayScene {
+    piano_keyboard: PianoKeyboard,
+    rectangle_pipeline: RectanglePipeline,
+
+    input: Option<InputManager>,
+    recorder: PerformanceRecorder,
+
+    start: Instant,
+    bars: Vec<Bar>,
+
+    /// Devices in use that got unplugged
+    lost_devices: Vec<String>,
+}
+
+impl FreePlayScene {
+    pub fn new(target: &mut Target) -> Self {
+        let keyboard_range = target.state.config.keyboard_range.resolve(None);
+        let piano_keyboard = PianoKeyboard::new(target, keyboard_range);
+
+        let input = InputManager::new(target.state.config.midi_input.as_deref());
+        if input.is_none() {
+            log::warn!("No MIDI input available for free play");
+        }
+
+        Self {
+            piano_keyboard,
+            rectangle_pipeline: RectanglePipeline::new(&target.gpu, &target.transform_uniform),
+
+            input,
+            recorder: PerformanceRecorder::new(),
+
+            start: Instant::now(),
+            bars: Vec::new(),
+
+            lost_devices: Vec::new(),
+        }
+    }
+
+    fn time(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+
+    fn check_devices(&mut self, target: &mut Target) {
+        let output_event = target.state.output_manager.check_devices();
+        let mut events: Vec<ConnectionEvent> = output_event.into_iter().collect();
+        if let Some(input) = &mut self.input {
+            events.extend(input.check_devices());
+        }
+
+        for event in events {
+            match event {
+                ConnectionEvent::Lost(name) => {
+                    // Keys released while unplugged would never end their bars
+                    for bar in self.bars.iter_mut().filter(|b| b.end.is_none()) {
+                        bar.end = Some(self.start.elapsed().as_secs_f32());
+                    }
+                    self.lost_devices.push(name);
+                }
+                ConnectionEvent::Restored(name) => {
+                    self.lost_devices.retain(|n| *n != name);
+                }
+            }
+        }
+    }
+
+    fn handle_input(&mut self, target: &mut Target) {
+        let input = match &self.input {
+            Some(input) => input,
+            None => return,
+        };
+
+        let mut events = Vec::new();
+        while let Some(event) = input.try_recv() {
+            events.push(event);
+        }
+
+        let input_latency = target.state.config.input_latency;
+
+        for (stamp, event) in events {
+            let time = stamp.saturating_duration_since(self.start).as_secs_f32() - input_latency;
+            self.note_event(target, time, event);
+        }
+    }
+
+    /// Plays, records and draws the note, `time` is in seconds since the scene start
+    fn note_event(&mut self, target: &mut Target, time: f32, event: InputEvent) {
+        match event {
+            InputEvent::NoteOn { key, vel } => {
+                target.state.output_manager.note_on(0, key, vel);
+                self.recorder.note_on(time, key, vel);
+
+                self.bars.push(Bar {
+                    key,
+                    start: time,
+                    end: None,
+                });
+            }
+            InputEvent::NoteOff { key } => {
+                target.state.output_manager.note_off(0, key);
+                self.recorder.note_off(time, key);
+
+                for bar in self.bars.iter_mut().filter(|b| b.key == key) {
+                    if bar.end.is_none() {
+                        bar.end = Some(time);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Scene for FreePlayScene {
+    fn scene_type(&self) -> SceneType {
+        SceneType::FreePlay
+    }
+
+    fn done(self: Box<Self>, target: &mut Target) {
+        target.state.output_manager.panic();
+
+        let tempo_map = lib_midi::TempoMap::new(RECORDING_PPQ);
+        self.recorder.save(&tempo_map, None);
+    }
+
+    fn resize(&mut self, target: &mut Target) {
+        self.piano_keyboard.resize(target);
+    }
+
+    fn update(&mut self, target: &mut Target) -> SceneEvent {
+        let (window_w, window_h) = {
+            let winit::dpi::LogicalSize { width, height } = target.window.state.logical_size;
+            (width, height)
+        };
+
+        self.check_devices(target);
+        self.handle_input(target);
+
+        let now = self.time();
+        let keyboard_top = window_h - window_h / 5.0;
+
+        // Forget bars that already left the screen
+        self.bars.retain(|bar| match bar.end {
+            Some(end) => (now - end) * SCROLL_SPEED < keyboard_top,
+            None => true,
+        });
+
+        let range = self.piano_keyboard.range();
+        let color_schema = &target.state.config.color_schema;
+        let color = &color_schema[0];
+
+        let mut notes_state = [(false, 0); 88];
+        let mut rectangles = Vec::new();
+
+        for bar in self.bars.iter() {
+            let id = match range.id(bar.key) {
+                Some(id) => id,
+                None => continue,
+            };
+            let key = &self.piano_keyboard.all_keys[id];
+
+            if bar.end.is_none() {
+                notes_state[id] = (true, 0);
+            }
+
+            let top = keyboard_top - (now - bar.start) * SCROLL_SPEED;
+            let bottom = keyboard_top - (now - bar.end.unwrap_or(now)) * SCROLL_SPEED;
+
+            let color: Color = if KeyRange::is_black(bar.key) {
+                color.dark.into()
+            } else {
+                color.base.into()
+            };
+
+            rectangles.push(RectangleInstance {
+                position: [key.x, top.max(0.0)],
+                size: [key.w - 1.0, (bottom - top.max(0.0)).max(1.0)],
+                color: color.into_linear_rgba(),
+            });
+        }
+
+        self.piano_keyboard.update_notes_state(target, notes_state);
+
+        self.rectangle_pipeline.update_instance_buffer(
+            &mut target.gpu.encoder,
+            &target.gpu.device,
+            rectangles,
+        );
+
+        let notice = if self.input.is_none() {
+            Some("No MIDI input found".to_string())
+        } else if !self.lost_devices.is_empty() {
+            Some(format!("Disconnected: {}", self.lost_devices.join(", ")))
+        } else {
+            None
+        };
+
+        if let Some(notice) = notice {
+            let text = vec![wgpu_glyph::Text::new(&notice)
+                .with_color([1.0, 1.0, 1.0, 1.0])
+                .with_scale(30.0)];
+
+            target.text_renderer.queue_text(wgpu_glyph::Section {
+                text,
+                screen_position: (window_w / 2.0, window_h / 3.0),
+                layout: wgpu_glyph::Layout::Wrap {
+                    line_breaker: Default::default(),
+                    h_align: wgpu_glyph::HorizontalAlign::Center,
+                    v_align: wgpu_glyph::VerticalAlign::Center,
+                },
+                ..Default::default()
+            });
+        }
+
+        SceneEvent::None
+    }
+
+    fn render(&mut self, target: &mut Target, view: &wgpu::TextureView) {
+        let transform_uniform = &target.transform_uniform;
+        let encoder = &mut target.gpu.encoder;
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                    attachment: view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                }],
+                depth_stencil_attachment: None,
+            });
+
+            self.rectangle_pipeline
+                .render(transform_uniform, &mut render_pass);
+            self.piano_keyboard
+                .render(transform_uniform, &mut render_pass);
+        }
+    }
+
+    fn window_event(&mut self, target: &mut Target, event: &WindowEvent) -> SceneEvent {
+        for e in self.piano_keyboard.pointer_event(target, event) {
+            match &self.input {
+                Some(input) => input.send(e),
+                None => {
+                    let time = self.time();
+                    self.note_event(target, time, e);
+                }
+            }
+        }
+
+        if let WindowEvent::KeyboardInput { input, .. } = event {
+            if let Some(manager) = &mut self.input {
+                if manager.key_event(input, target.window.state.modifers_state) {
+                    return SceneEvent::None;
+                }
+            }
+
+            if let (
+                winit::event::ElementState::Released,
+                Some(winit::event::VirtualKeyCode::Escape),
+            ) = (input.state, input.virtual_keycode)
+            {
+                return SceneEvent::GoBack;
+            }
+        }
+
+        SceneEvent::None
+    }
+}
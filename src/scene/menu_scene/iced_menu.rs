@@ -1,18 +1,22 @@
 use std::path::PathBuf;
 
 use iced_native::{
-    image, Align, Color, Column, Command, Container, Element, HorizontalAlignment, Image, Length,
-    Program, Row, Text, VerticalAlignment,
+    image, scrollable, Align, Checkbox, Color, Column, Command, Container, Element,
+    HorizontalAlignment, Image, Length, Program, Row, Scrollable, Text, VerticalAlignment,
 };
 use iced_wgpu::Renderer;
 
 use crate::main_state::MainState;
 use crate::output_manager::OutputDescriptor;
+use crate::playlist::Playlist;
+use crate::track_mixer::{TrackMixer, TrackToggle};
 
 use super::neo_btn::{self, NeoBtn};
 
 enum Controls {
     SongSelect(SongSelectControls),
+    Tracks(TrackControls),
+    Playlist(PlaylistControls),
     Exit(ExitControls),
 }
 
@@ -21,8 +25,17 @@ pub struct IcedMenu {
 
     midi_file: bool,
     font_path: Option<PathBuf>,
-
-    pub carousel: Carousel,
+    track_mixer: TrackMixer,
+    playlist: Playlist,
+    last_score: Option<String>,
+    progress: Vec<String>,
+
+    pub carousel: Carousel<OutputDescriptor>,
+    pub input_carousel: Carousel<String>,
+    /// Input saved in settings, selected once it is plugged in
+    saved_input: Option<String>,
+    /// Input whose MIDI clock drives playback
+    clock_input: Option<String>,
 
     controls: Controls,
 }
@@ -30,11 +43,26 @@ pub struct IcedMenu {
 #[derive(Debug, Clone)]
 pub enum Message {
     FileSelectPressed,
+    FolderSelectPressed,
 
     FontSelectPressed,
 
+    TracksPressed,
+    CalibratePressed,
+    FreePlayPressed,
+    TrackToggled(usize, TrackToggle, bool),
+
+    PlaylistPressed,
+    /// Song id, true when moved up
+    PlaylistSongMoved(usize, bool),
+    ShufflePressed,
+    RepeatToggled(bool),
+
     PrevPressed,
     NextPressed,
+    InputPrevPressed,
+    InputNextPressed,
+    ClockInputToggled(bool),
 
     #[cfg(feature = "play_along")]
     TogglePlayAlong(bool),
@@ -43,11 +71,21 @@ pub enum Message {
     EscPressed,
 
     MidiFileUpdate(bool),
+    TrackMixerUpdate(TrackMixer),
+    PlaylistUpdate(Playlist),
+    ProgressUpdate(Vec<String>),
     OutputsUpdated(Vec<OutputDescriptor>),
+    InputsUpdated(Vec<String>),
 
     // Output
     OutputFileSelected(PathBuf),
     OutputMainMenuDone(OutputDescriptor),
+    OutputTrackMixer(TrackMixer),
+    OutputPlaylist(Playlist),
+    OutputCalibrate(OutputDescriptor),
+    OutputInputSelected(String),
+    OutputClockInputSelected(Option<String>),
+    OutputFreePlay(OutputDescriptor),
     OutputAppExit,
 }
 
@@ -62,6 +100,12 @@ impl IcedMenu {
             carousel.id = id;
         }
 
+        let mut input_carousel = Carousel::new();
+        input_carousel.update(crate::input_manager::get_inputs());
+        if let Some(name) = &state.config.midi_input {
+            input_carousel.select(name);
+        }
+
         Self {
             #[cfg(feature = "play_along")]
             play_along: state.config.play_along,
@@ -70,12 +114,68 @@ impl IcedMenu {
 
             midi_file: state.midi_file.is_some(),
             font_path: state.output_manager.selected_font_path.clone(),
+            track_mixer: state.track_mixer.clone(),
+            playlist: state.playlist.clone(),
+            last_score: state.last_score.as_ref().map(|report| {
+                let total = &report.total;
+                let mut s = format!(
+                    "Last score: {} {}%, longest streak {}",
+                    total.grade(),
+                    (total.accuracy() * 100.0).round(),
+                    total.longest_streak
+                );
+
+                let weakest = report
+                    .sections
+                    .iter()
+                    .filter(|(_, stats)| stats.hits + stats.misses + stats.wrong_notes > 0)
+                    .min_by(|(_, a), (_, b)| {
+                        a.accuracy()
+                            .partial_cmp(&b.accuracy())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+
+                if let Some((section, stats)) = weakest {
+                    s += &format!(", weakest section {} ({})", section.name, stats.grade());
+                }
+
+                s
+            }),
+            progress: state
+                .practice_history
+                .summary(state.midi_file.as_ref().map(|m| m.content_hash)),
 
             carousel,
+            input_carousel,
+            saved_input: state.config.midi_input.clone(),
+            clock_input: state.config.midi_clock_input.clone(),
 
             controls: Controls::SongSelect(SongSelectControls::new()),
         }
     }
+
+    fn input_selected(&mut self) -> Command<Message> {
+        self.saved_input = self.input_carousel.get_item().cloned();
+
+        match self.saved_input.clone() {
+            Some(name) => Command::from(async { Message::OutputInputSelected(name) }),
+            None => Command::none(),
+        }
+    }
+
+    fn playlist_changed(&self) -> Command<Message> {
+        let playlist = self.playlist.clone();
+        Command::from(async { Message::OutputPlaylist(playlist) })
+    }
+
+    /// Output chosen in the carousel, synth gets the selected soundfont
+    fn selected_output(&self) -> Option<OutputDescriptor> {
+        self.carousel.get_item().map(|port| match port {
+            #[cfg(feature = "synth")]
+            OutputDescriptor::Synth(_) => OutputDescriptor::Synth(self.font_path.clone()),
+            _ => port.clone(),
+        })
+    }
 }
 
 impl Program for IcedMenu {
@@ -87,7 +187,7 @@ impl Program for IcedMenu {
             Message::FileSelectPressed => {
                 use nfd2::Response;
 
-                match nfd2::DialogBuilder::single()
+                match nfd2::DialogBuilder::multiple()
                     .filter("mid,midi")
                     .open()
                     .expect("File Dialog Error")
@@ -97,6 +197,36 @@ impl Program for IcedMenu {
 
                         return Command::from(async { Message::OutputFileSelected(path) });
                     }
+                    Response::OkayMultiple(mut paths) => {
+                        log::info!("File paths = {:?}", paths);
+
+                        if paths.len() == 1 {
+                            let path = paths.remove(0);
+                            return Command::from(async { Message::OutputFileSelected(path) });
+                        } else if !paths.is_empty() {
+                            let playlist = Playlist::new(paths);
+                            return Command::from(async { Message::OutputPlaylist(playlist) });
+                        }
+                    }
+                    _ => {
+                        log::error!("User canceled dialog");
+                    }
+                }
+            }
+            Message::FolderSelectPressed => {
+                use nfd2::Response;
+
+                match nfd2::open_pick_folder(None).expect("Folder Dialog Error") {
+                    Response::Okay(dir) => {
+                        log::info!("Folder path = {:?}", dir);
+
+                        match Playlist::from_folder(&dir) {
+                            Ok(playlist) => {
+                                return Command::from(async { Message::OutputPlaylist(playlist) });
+                            }
+                            Err(err) => log::error!("{}", err),
+                        }
+                    }
                     _ => {
                         log::error!("User canceled dialog");
                     }
@@ -121,6 +251,47 @@ impl Program for IcedMenu {
                 }
             }
 
+            Message::TracksPressed => {
+                if self.midi_file {
+                    self.controls = Controls::Tracks(TrackControls::new());
+                }
+            }
+            Message::PlaylistPressed => {
+                if !self.playlist.is_empty() {
+                    self.controls = Controls::Playlist(PlaylistControls::new());
+                }
+            }
+            Message::PlaylistSongMoved(id, up) => {
+                self.playlist.move_song(id, up);
+                return self.playlist_changed();
+            }
+            Message::ShufflePressed => {
+                self.playlist.shuffle();
+                return self.playlist_changed();
+            }
+            Message::RepeatToggled(is) => {
+                self.playlist.repeat = is;
+                return self.playlist_changed();
+            }
+            Message::CalibratePressed => {
+                if let Some(port) = self.selected_output() {
+                    return Command::from(async { Message::OutputCalibrate(port) });
+                }
+            }
+            Message::FreePlayPressed => {
+                if let Some(port) = self.selected_output() {
+                    return Command::from(async { Message::OutputFreePlay(port) });
+                }
+            }
+            Message::TrackToggled(id, toggle, is) => {
+                if let Some(track) = self.track_mixer.get_mut(id) {
+                    *track.flag_mut(toggle) = is;
+
+                    let mixer = self.track_mixer.clone();
+                    return Command::from(async { Message::OutputTrackMixer(mixer) });
+                }
+            }
+
             Message::NextPressed => {
                 if self.carousel.check_next() {
                     self.carousel.next();
@@ -131,6 +302,24 @@ impl Program for IcedMenu {
                     self.carousel.prev();
                 }
             }
+            Message::InputNextPressed => {
+                self.input_carousel.next();
+                return self.input_selected();
+            }
+            Message::InputPrevPressed => {
+                self.input_carousel.prev();
+                return self.input_selected();
+            }
+            Message::ClockInputToggled(is) => {
+                self.clock_input = if is {
+                    self.input_carousel.get_item().cloned()
+                } else {
+                    None
+                };
+
+                let name = self.clock_input.clone();
+                return Command::from(async { Message::OutputClockInputSelected(name) });
+            }
             #[cfg(feature = "play_along")]
             Message::TogglePlayAlong(is) => {
                 self.play_along = is;
@@ -156,6 +345,9 @@ impl Program for IcedMenu {
                         }
                     }
                 }
+                Controls::Tracks(_) | Controls::Playlist(_) => {
+                    self.controls = Controls::SongSelect(SongSelectControls::new());
+                }
                 Controls::Exit(_) => {
                     return Command::from(async { Message::OutputAppExit });
                 }
@@ -165,19 +357,32 @@ impl Program for IcedMenu {
                 Controls::SongSelect(_) => {
                     self.controls = Controls::Exit(ExitControls::new());
                 }
-                Controls::Exit(_) => {
+                Controls::Tracks(_) | Controls::Playlist(_) | Controls::Exit(_) => {
                     self.controls = Controls::SongSelect(SongSelectControls::new());
                 }
             },
 
             Message::MidiFileUpdate(is) => self.midi_file = is,
+            Message::TrackMixerUpdate(mixer) => self.track_mixer = mixer,
+            Message::PlaylistUpdate(playlist) => self.playlist = playlist,
+            Message::ProgressUpdate(progress) => self.progress = progress,
 
             Message::OutputsUpdated(outs) => {
                 self.carousel.update(outs);
             }
+            Message::InputsUpdated(inputs) => {
+                self.input_carousel
+                    .update_prefer(inputs, self.saved_input.as_ref());
+            }
 
             Message::OutputFileSelected(_) => {}
             Message::OutputMainMenuDone(_) => {}
+            Message::OutputTrackMixer(_) => {}
+            Message::OutputPlaylist(_) => {}
+            Message::OutputCalibrate(_) => {}
+            Message::OutputInputSelected(_) => {}
+            Message::OutputClockInputSelected(_) => {}
+            Message::OutputFreePlay(_) => {}
             Message::OutputAppExit => {}
         }
 
@@ -187,9 +392,18 @@ impl Program for IcedMenu {
     fn view(&mut self) -> Element<Message, Renderer> {
         let (controls, footer) = match &mut self.controls {
             Controls::SongSelect(c) => {
-                let (content, footer) = c.view(&mut self.carousel, self.midi_file, self.play_along);
+                let (content, footer) = c.view(
+                    &mut self.carousel,
+                    &self.input_carousel,
+                    self.clock_input.as_deref(),
+                    self.midi_file,
+                    self.playlist.len() > 1,
+                    self.play_along,
+                );
                 (content, Some(footer))
             }
+            Controls::Tracks(c) => (c.view(&self.track_mixer), None),
+            Controls::Playlist(c) => (c.view(&self.playlist), None),
             Controls::Exit(c) => (c.view(), None),
         };
 
@@ -202,12 +416,24 @@ impl Program for IcedMenu {
                 .center_y()
                 .width(Length::Fill);
 
+            let mut info = Column::new().width(Length::Fill).spacing(5);
+            for line in self.last_score.iter().chain(self.progress.iter()) {
+                info = info.push(
+                    Text::new(line.as_str())
+                        .color(Color::WHITE)
+                        .size(20)
+                        .width(Length::Fill)
+                        .horizontal_alignment(HorizontalAlignment::Center),
+                );
+            }
+
             let main = Column::new()
                 .width(Length::Fill)
                 .spacing(40)
                 .max_width(650)
                 .push(image)
-                .push(controls);
+                .push(controls)
+                .push(info);
 
             let centered_main = Container::new(main)
                 .width(Length::Fill)
@@ -228,15 +454,15 @@ impl Program for IcedMenu {
     }
 }
 
-pub struct Carousel {
-    outputs: Vec<OutputDescriptor>,
+pub struct Carousel<T> {
+    items: Vec<T>,
     id: usize,
 }
 
-impl Carousel {
+impl<T> Carousel<T> {
     fn new() -> Self {
         Self {
-            outputs: Vec::new(),
+            items: Vec::new(),
             id: 0,
         }
     }
@@ -245,12 +471,14 @@ impl Carousel {
         self.id
     }
 
-    fn update(&mut self, outs: Vec<OutputDescriptor>) {
-        self.outputs = outs;
+    /// Selection stays in bounds if the list got shorter
+    fn update(&mut self, items: Vec<T>) {
+        self.items = items;
+        self.id = self.id.min(self.items.len().saturating_sub(1));
     }
 
     fn check_next(&self) -> bool {
-        self.id < self.outputs.len() - 1
+        self.id + 1 < self.items.len()
     }
 
     fn check_prev(&self) -> bool {
@@ -269,21 +497,53 @@ impl Carousel {
         if self.check_prev() {
             self.id -= 1;
         } else {
-            self.id = self.outputs.len() - 1;
+            self.id = self.items.len().saturating_sub(1);
+        }
+    }
+
+    fn get_item(&self) -> Option<&T> {
+        self.items.get(self.id)
+    }
+}
+
+impl<T: PartialEq + Clone> Carousel<T> {
+    fn select(&mut self, item: &T) {
+        if let Some(id) = self.items.iter().position(|i| i == item) {
+            self.id = id;
         }
     }
 
-    fn get_item(&self) -> Option<&OutputDescriptor> {
-        self.outputs.get(self.id)
+    /// Keeps the same item selected even if it moved in the list
+    fn update_keep_selected(&mut self, items: Vec<T>) {
+        let selected = self.get_item().cloned();
+        self.update(items);
+        if let Some(item) = selected {
+            self.select(&item);
+        }
+    }
+
+    /// Like `update_keep_selected`, but switches to `preferred` once it is in the list
+    fn update_prefer(&mut self, items: Vec<T>, preferred: Option<&T>) {
+        self.update_keep_selected(items);
+        if let Some(item) = preferred {
+            self.select(item);
+        }
     }
 }
 
 #[derive(Default)]
 struct SongSelectControls {
     file_select_button: neo_btn::State,
+    folder_select_button: neo_btn::State,
+    tracks_button: neo_btn::State,
+    playlist_button: neo_btn::State,
+    calibrate_button: neo_btn::State,
+    free_play_button: neo_btn::State,
     synth_button: neo_btn::State,
     prev_button: neo_btn::State,
     next_button: neo_btn::State,
+    input_prev_button: neo_btn::State,
+    input_next_button: neo_btn::State,
     play_button: neo_btn::State,
 }
 
@@ -293,22 +553,69 @@ impl SongSelectControls {
     }
     fn view(
         &mut self,
-        carousel: &mut Carousel,
+        carousel: &mut Carousel<OutputDescriptor>,
+        input_carousel: &Carousel<String>,
+        clock_input: Option<&str>,
         midi_file: bool,
+        has_playlist: bool,
         play_along: bool,
     ) -> (Element<Message, Renderer>, Element<Message, Renderer>) {
-        let file_select_button = Row::new().height(Length::Units(100)).push(
-            NeoBtn::new(
-                &mut self.file_select_button,
-                Text::new("Select File")
-                    .size(40)
-                    .horizontal_alignment(HorizontalAlignment::Center)
-                    .vertical_alignment(VerticalAlignment::Center),
+        let mut file_select_button = Row::new()
+            .height(Length::Units(100))
+            .push(
+                NeoBtn::new(
+                    &mut self.file_select_button,
+                    Text::new("Select File")
+                        .size(40)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .on_press(Message::FileSelectPressed),
             )
-            .width(Length::Fill)
-            .height(Length::Fill)
-            .on_press(Message::FileSelectPressed),
-        );
+            .push(
+                NeoBtn::new(
+                    &mut self.folder_select_button,
+                    Text::new("Folder")
+                        .size(20)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Units(100))
+                .height(Length::Fill)
+                .on_press(Message::FolderSelectPressed),
+            );
+
+        if midi_file {
+            file_select_button = file_select_button.push(
+                NeoBtn::new(
+                    &mut self.tracks_button,
+                    Text::new("Tracks")
+                        .size(20)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Units(100))
+                .height(Length::Fill)
+                .on_press(Message::TracksPressed),
+            );
+        }
+
+        if has_playlist {
+            file_select_button = file_select_button.push(
+                NeoBtn::new(
+                    &mut self.playlist_button,
+                    Text::new("Playlist")
+                        .size(20)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Units(100))
+                .height(Length::Fill)
+                .on_press(Message::PlaylistPressed),
+            );
+        }
 
         let item = carousel.get_item();
 
@@ -364,14 +671,105 @@ impl SongSelectControls {
             .on_press(Message::NextPressed),
         );
 
+        let input_label = input_carousel
+            .get_item()
+            .map(|name| format!("Input: {}", name))
+            .unwrap_or_else(|| "No MIDI Input".to_string());
+
+        let mut input_row = Row::new()
+            .height(Length::Units(40))
+            .align_items(Align::Center)
+            .push(
+                NeoBtn::new(
+                    &mut self.input_prev_button,
+                    Text::new("<")
+                        .size(30)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Units(50))
+                .height(Length::Fill)
+                .disabled(!input_carousel.check_prev())
+                .on_press(Message::InputPrevPressed),
+            )
+            .push(
+                Text::new(input_label)
+                    .color(Color::WHITE)
+                    .size(20)
+                    .width(Length::Fill)
+                    .horizontal_alignment(HorizontalAlignment::Center)
+                    .vertical_alignment(VerticalAlignment::Center),
+            )
+            .push(
+                NeoBtn::new(
+                    &mut self.input_next_button,
+                    Text::new(">")
+                        .size(30)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Units(50))
+                .height(Length::Fill)
+                .disabled(!input_carousel.check_next())
+                .on_press(Message::InputNextPressed),
+            );
+
+        // Playback can follow MIDI clock of a real device only
+        let input = input_carousel
+            .get_item()
+            .filter(|name| name.as_str() != crate::input_manager::VIRTUAL_KEYBOARD_NAME);
+        if let Some(input) = input {
+            input_row = input_row
+                .push(
+                    Checkbox::new(
+                        clock_input == Some(input.as_str()),
+                        "",
+                        Message::ClockInputToggled,
+                    )
+                    .style(CheckboxStyle {}),
+                )
+                .push(Text::new("Clock").color(Color::WHITE).size(20));
+        }
+
+        let calibrate_button = NeoBtn::new(
+            &mut self.calibrate_button,
+            Text::new("Calibrate Latency")
+                .size(20)
+                .horizontal_alignment(HorizontalAlignment::Center)
+                .vertical_alignment(VerticalAlignment::Center),
+        )
+        .width(Length::Units(200))
+        .height(Length::Units(40))
+        .disabled(item.is_none())
+        .on_press(Message::CalibratePressed);
+
+        let free_play_button = NeoBtn::new(
+            &mut self.free_play_button,
+            Text::new("Free Play")
+                .size(20)
+                .horizontal_alignment(HorizontalAlignment::Center)
+                .vertical_alignment(VerticalAlignment::Center),
+        )
+        .width(Length::Units(200))
+        .height(Length::Units(40))
+        .disabled(item.is_none())
+        .on_press(Message::FreePlayPressed);
+
+        let extra_row = Row::new()
+            .spacing(20)
+            .push(free_play_button)
+            .push(calibrate_button);
+
         let controls = Column::new()
             .align_items(Align::Center)
             .width(Length::Units(500))
-            .height(Length::Units(250))
+            .height(Length::Units(390))
             .spacing(30)
             .push(file_select_button)
             .push(output)
-            .push(select_row);
+            .push(select_row)
+            .push(input_row)
+            .push(extra_row);
 
         (
             Container::new(controls)
@@ -385,7 +783,7 @@ impl SongSelectControls {
     #[allow(unused_variables)]
     fn footer<'a>(
         play_button: &'a mut neo_btn::State,
-        carousel: &Carousel,
+        carousel: &Carousel<OutputDescriptor>,
         midi_file: bool,
         play_along: bool,
     ) -> Element<'a, Message, Renderer> {
@@ -408,7 +806,6 @@ impl SongSelectControls {
 
             #[cfg(feature = "play_along")]
             {
-                use iced_native::Checkbox;
                 coll = coll.push(
                     Row::new()
                         .height(Length::Shrink)
@@ -435,6 +832,194 @@ impl SongSelectControls {
     }
 }
 
+#[derive(Default)]
+struct TrackControls {
+    scroll: scrollable::State,
+    back_button: neo_btn::State,
+}
+
+impl TrackControls {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn view(&mut self, track_mixer: &TrackMixer) -> Element<Message, Renderer> {
+        let mut list = Scrollable::new(&mut self.scroll)
+            .width(Length::Fill)
+            .height(Length::Units(300))
+            .spacing(10);
+
+        for (id, track) in track_mixer.tracks().iter().enumerate() {
+            let checkbox = |is: bool, label: &str, toggle: TrackToggle| {
+                Checkbox::new(is, label, move |is| Message::TrackToggled(id, toggle, is))
+                    .text_size(20)
+                    .style(CheckboxStyle {})
+            };
+
+            let name = Text::new(format!(
+                "{}. {} ({} notes)",
+                id + 1,
+                track.name,
+                track.notes_count
+            ))
+            .color(Color::WHITE)
+            .size(20)
+            .width(Length::Fill);
+
+            let row = Row::new()
+                .spacing(10)
+                .align_items(Align::Center)
+                .push(name)
+                .push(checkbox(track.muted, "Mute", TrackToggle::Mute))
+                .push(checkbox(track.solo, "Solo", TrackToggle::Solo))
+                .push(checkbox(track.hidden, "Hide", TrackToggle::Hide))
+                .push(checkbox(track.user_plays, "I play", TrackToggle::UserPlays));
+
+            list = list.push(row);
+        }
+
+        let back_button = NeoBtn::new(
+            &mut self.back_button,
+            Text::new("Back")
+                .size(30)
+                .horizontal_alignment(HorizontalAlignment::Center)
+                .vertical_alignment(VerticalAlignment::Center),
+        )
+        .width(Length::Fill)
+        .height(Length::Units(50))
+        .on_press(Message::EscPressed);
+
+        let controls = Column::new()
+            .align_items(Align::Center)
+            .width(Length::Units(650))
+            .spacing(30)
+            .push(list)
+            .push(back_button);
+
+        Container::new(controls)
+            .width(Length::Fill)
+            .center_x()
+            .into()
+    }
+}
+
+#[derive(Default)]
+struct PlaylistControls {
+    scroll: scrollable::State,
+    /// Up and down buttons of every song
+    move_buttons: Vec<(neo_btn::State, neo_btn::State)>,
+    shuffle_button: neo_btn::State,
+    back_button: neo_btn::State,
+}
+
+impl PlaylistControls {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn view(&mut self, playlist: &Playlist) -> Element<Message, Renderer> {
+        let mut list = Scrollable::new(&mut self.scroll)
+            .width(Length::Fill)
+            .height(Length::Units(300))
+            .spacing(10);
+
+        let last = playlist.len().saturating_sub(1);
+        self.move_buttons
+            .resize_with(playlist.len(), Default::default);
+
+        for (id, (path, (up_button, down_button))) in playlist
+            .songs()
+            .iter()
+            .zip(self.move_buttons.iter_mut())
+            .enumerate()
+        {
+            let file_name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            let color = if id == playlist.current_id() {
+                Color::from_rgba8(160, 81, 255, 1.0)
+            } else {
+                Color::WHITE
+            };
+
+            let name = Text::new(format!("{}. {}", id + 1, file_name))
+                .color(color)
+                .size(20)
+                .width(Length::Fill);
+
+            let move_button = |state, label, up, disabled| {
+                NeoBtn::new(
+                    state,
+                    Text::new(label)
+                        .size(20)
+                        .horizontal_alignment(HorizontalAlignment::Center)
+                        .vertical_alignment(VerticalAlignment::Center),
+                )
+                .width(Length::Units(40))
+                .height(Length::Units(30))
+                .disabled(disabled)
+                .on_press(Message::PlaylistSongMoved(id, up))
+            };
+
+            let row = Row::new()
+                .spacing(10)
+                .align_items(Align::Center)
+                .push(name)
+                .push(move_button(up_button, "^", true, id == 0))
+                .push(move_button(down_button, "v", false, id == last));
+
+            list = list.push(row);
+        }
+
+        let shuffle_button = NeoBtn::new(
+            &mut self.shuffle_button,
+            Text::new("Shuffle")
+                .size(30)
+                .horizontal_alignment(HorizontalAlignment::Center)
+                .vertical_alignment(VerticalAlignment::Center),
+        )
+        .width(Length::Fill)
+        .height(Length::Units(50))
+        .on_press(Message::ShufflePressed);
+
+        let repeat = Checkbox::new(playlist.repeat, "Repeat", Message::RepeatToggled)
+            .text_size(20)
+            .style(CheckboxStyle {});
+
+        let back_button = NeoBtn::new(
+            &mut self.back_button,
+            Text::new("Back")
+                .size(30)
+                .horizontal_alignment(HorizontalAlignment::Center)
+                .vertical_alignment(VerticalAlignment::Center),
+        )
+        .width(Length::Fill)
+        .height(Length::Units(50))
+        .on_press(Message::EscPressed);
+
+        let buttons = Row::new()
+            .spacing(20)
+            .align_items(Align::Center)
+            .push(shuffle_button)
+            .push(repeat)
+            .push(back_button);
+
+        let controls = Column::new()
+            .align_items(Align::Center)
+            .width(Length::Units(650))
+            .spacing(30)
+            .push(list)
+            .push(buttons);
+
+        Container::new(controls)
+            .width(Length::Fill)
+            .center_x()
+            .into()
+    }
+}
+
 #[derive(Default)]
 struct ExitControls {
     no_button: neo_btn::State,
@@ -527,3 +1112,80 @@ impl iced_graphics::checkbox::StyleSheet for CheckboxStyle {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn carousel(items: &[&str]) -> Carousel<String> {
+        let mut carousel = Carousel::new();
+        carousel.update(items.iter().map(|item| item.to_string()).collect());
+        carousel
+    }
+
+    fn selected(carousel: &Carousel<String>) -> Option<&str> {
+        carousel.get_item().map(String::as_str)
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around() {
+        let mut carousel = carousel(&["a", "b", "c"]);
+
+        carousel.prev();
+        assert_eq!(selected(&carousel), Some("c"));
+        carousel.next();
+        assert_eq!(selected(&carousel), Some("a"));
+        carousel.next();
+        assert_eq!(selected(&carousel), Some("b"));
+
+        let mut empty = Carousel::<String>::new();
+        empty.next();
+        empty.prev();
+        assert_eq!(selected(&empty), None);
+    }
+
+    #[test]
+    fn select_by_name() {
+        let mut carousel = carousel(&["Computer Keyboard", "Piano", "Pad"]);
+
+        carousel.select(&"Pad".to_string());
+        assert_eq!(carousel.id(), 2);
+
+        // Unknown input keeps the selection
+        carousel.select(&"Drums".to_string());
+        assert_eq!(selected(&carousel), Some("Pad"));
+    }
+
+    #[test]
+    fn selected_input_survives_reconnects() {
+        let mut carousel = carousel(&["Computer Keyboard", "Piano", "Pad"]);
+        carousel.select(&"Pad".to_string());
+
+        // Other device unplugged, selected one moved in the list
+        carousel.update_keep_selected(vec!["Computer Keyboard".into(), "Pad".into()]);
+        assert_eq!(selected(&carousel), Some("Pad"));
+
+        // Selected device unplugged, selection stays in bounds
+        carousel.update_keep_selected(vec!["Computer Keyboard".into()]);
+        assert_eq!(selected(&carousel), Some("Computer Keyboard"));
+    }
+
+    #[test]
+    fn saved_input_is_picked_once_plugged_in() {
+        // Saved input was missing at startup, first one stands in for it
+        let mut carousel = carousel(&["Computer Keyboard", "Pad"]);
+        let saved = "Piano".to_string();
+        carousel.update_prefer(vec!["Computer Keyboard".into(), "Pad".into()], Some(&saved));
+        assert_eq!(selected(&carousel), Some("Computer Keyboard"));
+
+        carousel.update_prefer(
+            vec!["Computer Keyboard".into(), "Pad".into(), "Piano".into()],
+            Some(&saved),
+        );
+        assert_eq!(selected(&carousel), Some("Piano"));
+
+        // Unplugged again, selection stays in bounds until it is back
+        carousel.update_prefer(vec!["Computer Keyboard".into(), "Pad".into()], Some(&saved));
+        assert_eq!(selected(&carousel), Some("Pad"));
+    }
+}
@@ -6,24 +6,33 @@ mod neo_btn;
 use bg_pipeline::BgPipeline;
 use iced_menu::IcedMenu;
 
+use std::time::{Duration, Instant};
+
 use winit::event::WindowEvent;
 
 use crate::{
+    input_manager::get_inputs,
+    playlist::Playlist,
     scene::{Scene, SceneEvent, SceneType},
     target::Target,
     time_manager::Timer,
     ui::iced_conversion,
 };
 
+const INPUTS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
 #[derive(Debug)]
 pub enum Event {
     Play,
+    Calibrate,
+    FreePlay,
 }
 
 pub struct MenuScene {
     bg_pipeline: BgPipeline,
     timer: Timer,
     iced_state: iced_native::program::State<IcedMenu>,
+    last_inputs_poll: Instant,
 }
 
 impl MenuScene {
@@ -46,11 +55,35 @@ impl MenuScene {
             bg_pipeline: BgPipeline::new(&target.gpu),
             timer,
             iced_state,
+            last_inputs_poll: Instant::now(),
         };
 
         scene.resize(target);
         scene
     }
+
+    /// Sends the selected song and everything derived from it to the menu
+    fn song_updated(&mut self, target: &Target) {
+        self.iced_state
+            .queue_message(iced_menu::Message::MidiFileUpdate(
+                target.state.midi_file.is_some(),
+            ));
+        self.iced_state
+            .queue_message(iced_menu::Message::TrackMixerUpdate(
+                target.state.track_mixer.clone(),
+            ));
+        self.iced_state
+            .queue_message(iced_menu::Message::ProgressUpdate(
+                target
+                    .state
+                    .practice_history
+                    .summary(target.state.midi_file.as_ref().map(|m| m.content_hash)),
+            ));
+        self.iced_state
+            .queue_message(iced_menu::Message::PlaylistUpdate(
+                target.state.playlist.clone(),
+            ));
+    }
 }
 
 impl Scene for MenuScene {
@@ -68,6 +101,13 @@ impl Scene for MenuScene {
         self.iced_state
             .queue_message(iced_menu::Message::OutputsUpdated(outs));
 
+        // Opening a MIDI client is not free, so inputs are not listed every frame
+        if self.last_inputs_poll.elapsed() >= INPUTS_POLL_INTERVAL {
+            self.last_inputs_poll = Instant::now();
+            self.iced_state
+                .queue_message(iced_menu::Message::InputsUpdated(get_inputs()));
+        }
+
         SceneEvent::None
     }
 
@@ -124,6 +164,12 @@ impl Scene for MenuScene {
                         winit::event::VirtualKeyCode::Right => self
                             .iced_state
                             .queue_message(iced_menu::Message::NextPressed),
+                        winit::event::VirtualKeyCode::Up => self
+                            .iced_state
+                            .queue_message(iced_menu::Message::InputPrevPressed),
+                        winit::event::VirtualKeyCode::Down => self
+                            .iced_state
+                            .queue_message(iced_menu::Message::InputNextPressed),
                         winit::event::VirtualKeyCode::Return => self
                             .iced_state
                             .queue_message(iced_menu::Message::EnterPressed),
@@ -159,18 +205,29 @@ impl Scene for MenuScene {
 
                     match event {
                         iced_menu::Message::OutputFileSelected(path) => {
-                            let midi = lib_midi::Midi::new(path.to_str().unwrap());
-
-                            if let Err(e) = &midi {
+                            // Single file replaces the playlist
+                            target.state.playlist = Playlist::default();
+                            if let Err(e) = target.state.load_midi_file(&path) {
                                 log::error!("{}", e);
                             }
 
-                            target.state.midi_file = midi.ok();
+                            self.song_updated(target);
+                        }
+                        iced_menu::Message::OutputPlaylist(playlist) => {
+                            if let Err(e) = target.state.set_playlist(playlist) {
+                                log::error!("{}", e);
+                            }
 
-                            self.iced_state
-                                .queue_message(iced_menu::Message::MidiFileUpdate(
-                                    target.state.midi_file.is_some(),
-                                ));
+                            self.song_updated(target);
+                        }
+                        iced_menu::Message::OutputTrackMixer(mixer) => {
+                            target.state.track_mixer = mixer;
+                        }
+                        iced_menu::Message::OutputInputSelected(name) => {
+                            target.state.config.midi_input = Some(name);
+                        }
+                        iced_menu::Message::OutputClockInputSelected(name) => {
+                            target.state.config.midi_clock_input = name;
                         }
                         iced_menu::Message::OutputMainMenuDone(out) => {
                             let program = self.iced_state.program();
@@ -186,6 +243,24 @@ impl Scene for MenuScene {
 
                             return SceneEvent::MainMenu(Event::Play);
                         }
+                        iced_menu::Message::OutputCalibrate(out) => {
+                            let program = self.iced_state.program();
+
+                            target.state.output_manager.selected_output_id =
+                                Some(program.carousel.id());
+                            target.state.output_manager.connect(out);
+
+                            return SceneEvent::MainMenu(Event::Calibrate);
+                        }
+                        iced_menu::Message::OutputFreePlay(out) => {
+                            let program = self.iced_state.program();
+
+                            target.state.output_manager.selected_output_id =
+                                Some(program.carousel.id());
+                            target.state.output_manager.connect(out);
+
+                            return SceneEvent::MainMenu(Event::FreePlay);
+                        }
                         iced_menu::Message::OutputAppExit => {
                             return SceneEvent::GoBack;
                         }
@@ -1,3 +1,5 @@
+pub mod calibration_scene;
+pub mod free_play_scene;
 pub mod menu_scene;
 
 pub mod playing_scene;
@@ -28,6 +30,8 @@ pub trait Scene {
 pub enum SceneType {
     MainMenu,
     Playing,
+    Calibration,
+    FreePlay,
     Transition,
 }
 
@@ -35,5 +39,7 @@ pub enum SceneType {
 pub enum SceneEvent {
     MainMenu(menu_scene::Event),
     GoBack,
+    /// Song ended and the playlist has another one
+    NextSong,
     None,
 }
@@ -0,0 +1,201 @@
+use std::collections::BTreeMap;
+
+use lib_midi::{ChannelEvent, ChannelMessage};
+
+use crate::output_manager::OutputManager;
+
+const RESET_ALL_CONTROLLERS: u8 = 121;
+
+/// Controllers that "Reset All Controllers" leaves alone, with their General MIDI defaults
+const NOT_RESET_CONTROLLERS: [(u8, u8); 4] = [
+    // Bank select
+    (0, 0),
+    // Volume
+    (7, 100),
+    // Pan
+    (10, 64),
+    // Expression
+    (11, 127),
+];
+
+#[derive(Default)]
+struct ChannelState {
+    program: Option<u8>,
+    /// `None` for controllers that are set only later in the song
+    controllers: BTreeMap<u8, Option<u8>>,
+    pitch_bend: Option<u16>,
+    pressure: Option<u8>,
+}
+
+/// Rebuilds program, controllers (pedals included) and pitch bend of every channel
+/// as they would be at `time` and sends them to the output
+pub fn chase_channel_state(
+    output_manager: &mut OutputManager,
+    channel_events: &[ChannelEvent],
+    time: f32,
+) {
+    for (ch, message) in chase_messages(channel_events, time) {
+        output_manager.channel_message(ch, &message);
+    }
+}
+
+/// Messages that bring every channel used by `channel_events` to its state at `time`,
+/// values set only after `time` go back to their defaults, so seeking backward undoes them
+fn chase_messages(channel_events: &[ChannelEvent], time: f32) -> Vec<(u8, ChannelMessage)> {
+    let mut channels: BTreeMap<u8, ChannelState> = BTreeMap::new();
+
+    for e in channel_events.iter() {
+        let state = channels.entry(e.ch).or_default();
+        let before = e.time < time;
+
+        match e.message {
+            ChannelMessage::ControlChange { controller, value } => {
+                let entry = state.controllers.entry(controller).or_insert(None);
+                if before {
+                    *entry = Some(value);
+                }
+            }
+            ChannelMessage::ProgramChange { program } if before => {
+                state.program = Some(program);
+            }
+            ChannelMessage::PitchBend { value } if before => {
+                state.pitch_bend = Some(value);
+            }
+            ChannelMessage::ChannelPressure { pressure } if before => {
+                state.pressure = Some(pressure);
+            }
+            _ => {}
+        }
+    }
+
+    let mut messages = Vec::new();
+    for (ch, state) in channels {
+        let mut send = |message| messages.push((ch, message));
+
+        send(ChannelMessage::ControlChange {
+            controller: RESET_ALL_CONTROLLERS,
+            value: 0,
+        });
+
+        for (controller, value) in state.controllers {
+            let value = value.or_else(|| {
+                NOT_RESET_CONTROLLERS
+                    .iter()
+                    .find(|(c, _)| *c == controller)
+                    .map(|(_, default)| *default)
+            });
+
+            if let Some(value) = value {
+                send(ChannelMessage::ControlChange { controller, value });
+            }
+        }
+
+        // Bank select has to come before program change,
+        // channel without a program change so far plays the first one
+        send(ChannelMessage::ProgramChange {
+            program: state.program.unwrap_or(0),
+        });
+        if let Some(value) = state.pitch_bend {
+            send(ChannelMessage::PitchBend { value });
+        }
+        if let Some(pressure) = state.pressure {
+            send(ChannelMessage::ChannelPressure { pressure });
+        }
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(time: f32, ch: u8, message: ChannelMessage) -> ChannelEvent {
+        ChannelEvent {
+            time,
+            ch,
+            track_id: 0,
+            message,
+        }
+    }
+
+    fn cc(controller: u8, value: u8) -> ChannelMessage {
+        ChannelMessage::ControlChange { controller, value }
+    }
+
+    fn program(program: u8) -> ChannelMessage {
+        ChannelMessage::ProgramChange { program }
+    }
+
+    /// Messages as comparable tuples (ch, kind, number, value)
+    fn chase(events: &[ChannelEvent], time: f32) -> Vec<(u8, &'static str, u16, u16)> {
+        chase_messages(events, time)
+            .into_iter()
+            .map(|(ch, message)| match message {
+                ChannelMessage::ControlChange { controller, value } => {
+                    (ch, "cc", u16::from(controller), u16::from(value))
+                }
+                ChannelMessage::ProgramChange { program } => (ch, "program", 0, u16::from(program)),
+                ChannelMessage::PitchBend { value } => (ch, "bend", 0, value),
+                ChannelMessage::ChannelPressure { pressure } => {
+                    (ch, "pressure", 0, u16::from(pressure))
+                }
+            })
+            .collect()
+    }
+
+    fn song() -> Vec<ChannelEvent> {
+        vec![
+            event(0.0, 0, cc(7, 90)),
+            event(0.0, 0, program(5)),
+            event(10.0, 0, cc(7, 40)),
+            event(10.0, 0, cc(64, 127)),
+            event(10.0, 0, program(30)),
+            event(10.0, 1, program(12)),
+        ]
+    }
+
+    #[test]
+    fn state_after_the_changes() {
+        assert_eq!(
+            chase(&song(), 12.0),
+            vec![
+                (0, "cc", 121, 0),
+                (0, "cc", 7, 40),
+                (0, "cc", 64, 127),
+                (0, "program", 0, 30),
+                (1, "cc", 121, 0),
+                (1, "program", 0, 12),
+            ]
+        );
+    }
+
+    #[test]
+    fn seeking_backward_undoes_later_changes() {
+        assert_eq!(
+            chase(&song(), 5.0),
+            vec![
+                (0, "cc", 121, 0),
+                (0, "cc", 7, 90),
+                // Sustain is reset by "Reset All Controllers"
+                (0, "program", 0, 5),
+                (1, "cc", 121, 0),
+                (1, "program", 0, 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn controllers_set_later_go_back_to_defaults() {
+        assert_eq!(
+            chase(&song(), 0.0),
+            vec![
+                (0, "cc", 121, 0),
+                (0, "cc", 7, 100),
+                (0, "program", 0, 0),
+                (1, "cc", 121, 0),
+                (1, "program", 0, 0),
+            ]
+        );
+    }
+}
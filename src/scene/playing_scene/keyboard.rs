@@ -1,97 +1,111 @@
+use std::collections::HashMap;
+
+use winit::event::{ElementState, MouseButton, TouchPhase, WindowEvent};
+
 use super::keyboard_pipeline::{KeyInstance, KeyStateInstance, KeyboardPipeline};
+use crate::input_manager::InputEvent;
+use crate::keyboard_range::KeyRange;
 use crate::target::Target;
 use crate::wgpu_jumpstart::Color;
 use crate::TransformUniform;
 use crate::Uniform;
 
-// const KEY_C: u8 = 0;
-const KEY_CIS: u8 = 1;
-// const KEY_D: u8 = 2;
-const KEY_DIS: u8 = 3;
-// const KEY_E: u8 = 4;
-// const KEY_F: u8 = 5;
-const KEY_FIS: u8 = 6;
-// const KEY_G: u8 = 7;
-const KEY_GIS: u8 = 8;
-// const KEY_A: u8 = 9;
-const KEY_AIS: u8 = 10;
-// const KEY_B: u8 = 11;
+/// Pointer id of the mouse, touches use their finger id
+const MOUSE_POINTER: u64 = u64::MAX;
+/// Velocity of keys pressed on the screen
+const POINTER_VELOCITY: u8 = 100;
 
 pub struct Key {
     pub x: f32,
+    pub y: f32,
     pub w: f32,
+    pub h: f32,
     pub is_black: bool,
 }
 
+impl Key {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+}
+
 pub struct PianoKeyboard {
     pub keyboard_pipeline: KeyboardPipeline,
     pub all_keys: Vec<Key>,
+    range: KeyRange,
+    /// Keys held down on the screen by mouse or fingers
+    pointers: HashMap<u64, u8>,
 }
 
 impl PianoKeyboard {
-    pub fn new(target: &mut Target) -> Self {
+    pub fn new(target: &mut Target, range: KeyRange) -> Self {
         let keyboard_pipeline = KeyboardPipeline::new(target);
         let mut piano_keyboard = Self {
             keyboard_pipeline,
             all_keys: Vec::new(),
+            range,
+            pointers: HashMap::new(),
         };
         piano_keyboard.resize(target);
 
         piano_keyboard
     }
+    pub fn range(&self) -> KeyRange {
+        self.range
+    }
+    pub fn set_range(&mut self, target: &mut Target, range: KeyRange) {
+        self.range = range;
+        self.resize(target);
+    }
     pub fn resize(&mut self, target: &mut Target) {
         let (window_w, window_h) = {
             let winit::dpi::LogicalSize { width, height } = target.window.state.logical_size;
             (width, height)
         };
 
-        let w = window_w / 34.0; // 22.0 // 36.0
+        let w = window_w / self.range.white_count() as f32;
         let h = window_h / 5.0;
 
-        let mut x_offset = 0.0;
+        // Count of white keys on the left side of the current key
+        let mut white_id = 0;
 
         self.all_keys.clear();
         let mut white_keys = Vec::new();
         let mut black_keys = Vec::new();
 
         let mut rectangles = Vec::new();
-        for id in 0..58 { //61
-            let x = id as f32 * w;
+        for note in self.range.iter() {
+            let x = white_id as f32 * w;
             let y = 0.0;
 
-            let key_id = id + 12;
-            let note_id = key_id % 12;
-
-            if note_id == KEY_CIS
-                || note_id == KEY_DIS
-                || note_id == KEY_FIS
-                || note_id == KEY_GIS
-                || note_id == KEY_AIS
-            {
-                x_offset -= w;
-
+            if KeyRange::is_black(note) {
                 let w = w / 1.5;
                 let h = h / 1.5;
 
                 let black_offset = w;
 
-                // let x = x_offset + black_offset + x + w / 2.0;
-                let x = x_offset + black_offset + x;
+                // Black key starts on the previous white key
+                let x = x - w * 1.5 + black_offset;
                 let y = y + window_h - h * 1.5;
 
                 self.all_keys.push(Key {
                     x,
+                    y,
                     w,
+                    h,
                     is_black: true,
                 });
                 black_keys.push((x, y, w, h));
             } else {
-                let x = x_offset + x;
+                white_id += 1;
+
                 let y = y + window_h - h;
 
                 self.all_keys.push(Key {
                     x,
+                    y,
                     w,
+                    h,
                     is_black: false,
                 });
                 white_keys.push((x, y, w, h));
@@ -117,22 +131,95 @@ impl PianoKeyboard {
         self.keyboard_pipeline
             .update_instance_buffer(&mut target.gpu, rectangles);
     }
+    /// Midi key under the given point, black keys are drawn on top so they win
+    pub fn key_at(&self, x: f32, y: f32) -> Option<u8> {
+        let keys = self.all_keys.iter().enumerate();
+        let (black, white): (Vec<_>, Vec<_>) = keys.partition(|(_, key)| key.is_black);
+
+        black
+            .into_iter()
+            .chain(white)
+            .find(|(_, key)| key.contains(x, y))
+            .map(|(id, _)| self.range.first() + id as u8)
+    }
+
+    /// Keys that are held down on the screen
+    pub fn pointer_notes(&self) -> impl Iterator<Item = &u8> {
+        self.pointers.values()
+    }
+
+    /// Turns mouse and touch presses on the keyboard into note events,
+    /// sliding over the keys plays each of them
+    pub fn pointer_event(&mut self, target: &Target, event: &WindowEvent) -> Vec<InputEvent> {
+        let scale_factor = target.window.state.scale_factor;
+
+        match event {
+            WindowEvent::MouseInput {
+                state,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let note = match state {
+                    ElementState::Pressed => {
+                        let pos = target.window.state.cursor_logical_position;
+                        self.key_at(pos.x, pos.y)
+                    }
+                    ElementState::Released => None,
+                };
+                self.move_pointer(MOUSE_POINTER, note)
+            }
+            WindowEvent::CursorMoved { position, .. }
+                if self.pointers.contains_key(&MOUSE_POINTER) =>
+            {
+                let pos = position.to_logical::<f32>(scale_factor);
+                let note = self.key_at(pos.x, pos.y);
+                self.move_pointer(MOUSE_POINTER, note)
+            }
+            WindowEvent::Touch(touch) => {
+                let note = match touch.phase {
+                    TouchPhase::Started | TouchPhase::Moved => {
+                        let pos = touch.location.to_logical::<f32>(scale_factor);
+                        self.key_at(pos.x, pos.y)
+                    }
+                    TouchPhase::Ended | TouchPhase::Cancelled => None,
+                };
+                self.move_pointer(touch.id, note)
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn move_pointer(&mut self, pointer: u64, note: Option<u8>) -> Vec<InputEvent> {
+        let old = self.pointers.get(&pointer).copied();
+        if old == note {
+            return Vec::new();
+        }
+
+        let mut events = Vec::new();
+        if let Some(key) = old {
+            self.pointers.remove(&pointer);
+            events.push(InputEvent::NoteOff { key });
+        }
+        if let Some(key) = note {
+            self.pointers.insert(pointer, key);
+            events.push(InputEvent::NoteOn {
+                key,
+                vel: POINTER_VELOCITY,
+            });
+        }
+
+        events
+    }
+
     pub fn update_notes_state(&mut self, target: &mut Target, notes: [(bool, usize); 88]) {
         let mut white_keys = Vec::new();
         let mut black_keys = Vec::new();
 
         // Becouse white keys are first in instance bufer we need to split input
-        for id in 0..58 {  //61
-            let key_id = id + 12;
-            let note_id = key_id % 12;
-            let note = notes[id as usize];
-
-            if note_id == KEY_CIS
-                || note_id == KEY_DIS
-                || note_id == KEY_FIS
-                || note_id == KEY_GIS
-                || note_id == KEY_AIS
-            {
+        for (id, key) in self.range.iter().enumerate() {
+            let note = notes[id];
+
+            if KeyRange::is_black(key) {
                 black_keys.push(note);
             } else {
                 white_keys.push(note);
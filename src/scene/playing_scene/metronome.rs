@@ -0,0 +1,164 @@
+use std::time::{Duration, Instant};
+
+use lib_midi::{Beat, Midi};
+
+use crate::output_manager::OutputManager;
+
+/// General MIDI percussion channel (channel 10), used for clicks of external devices
+const PERCUSSION_CH: u8 = 9;
+/// Hi Wood Block
+const ACCENT_KEY: u8 = 76;
+/// Low Wood Block
+const CLICK_KEY: u8 = 77;
+
+const CLICK_LENGTH: Duration = Duration::from_millis(50);
+
+struct CountIn {
+    clicks_left: u32,
+    beats_per_bar: u32,
+    interval: Duration,
+    next_click: Instant,
+}
+
+pub struct Metronome {
+    beats: Vec<Beat>,
+    /// Index of first beat that was not clicked yet
+    next_beat: usize,
+    last_time: f32,
+
+    count_in: Option<CountIn>,
+    /// Key of the click that is still sounding
+    sounding: Option<(u8, Instant)>,
+}
+
+impl Metronome {
+    pub fn new(midi: &Midi, song_end: f32) -> Self {
+        Self {
+            beats: midi.tempo_map.beats(song_end),
+            next_beat: 0,
+            last_time: 0.0,
+
+            count_in: None,
+            sounding: None,
+        }
+    }
+
+    pub fn beats(&self) -> &[Beat] {
+        &self.beats
+    }
+
+    fn click(&mut self, output_manager: &mut OutputManager, accent: bool) {
+        self.release(output_manager);
+
+        // Built-in synth clicks on its own, so the click does not mix with drums of the song
+        if output_manager.click(accent) {
+            return;
+        }
+
+        let (key, vel) = if accent {
+            (ACCENT_KEY, 127)
+        } else {
+            (CLICK_KEY, 100)
+        };
+
+        output_manager.note_on(PERCUSSION_CH, key, vel);
+        self.sounding = Some((key, Instant::now()));
+    }
+
+    fn release(&mut self, output_manager: &mut OutputManager) {
+        if let Some((key, _)) = self.sounding.take() {
+            output_manager.note_off(PERCUSSION_CH, key);
+        }
+    }
+
+    /// Clicks every beat that was passed since the last update
+    pub fn update(&mut self, output_manager: &mut OutputManager, enabled: bool, time: f32) {
+        if let Some((_, start)) = self.sounding {
+            if start.elapsed() >= CLICK_LENGTH {
+                self.release(output_manager);
+            }
+        }
+
+        // Playback jumped, don't click everything in between
+        if time < self.last_time || time - self.last_time > 0.5 {
+            self.seek(time);
+        }
+        self.last_time = time;
+
+        while let Some(beat) = self.beats.get(self.next_beat) {
+            if beat.time > time {
+                break;
+            }
+
+            let accent = beat.is_downbeat();
+            self.next_beat += 1;
+
+            if enabled {
+                self.click(output_manager, accent);
+            }
+        }
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.next_beat = self
+            .beats
+            .iter()
+            .position(|b| b.time >= time)
+            .unwrap_or_else(|| self.beats.len());
+        self.last_time = time;
+    }
+
+    /// Starts counting in `bars` bars in the tempo of the song at `time`
+    pub fn start_count_in(&mut self, midi: &Midi, bars: u8, speed_multiplier: f32, time: f32) {
+        if bars == 0 {
+            self.count_in = None;
+            return;
+        }
+
+        let (numerator, denominator) = midi.tempo_map.time_signature_at(time.max(0.0));
+        let quarter = 60.0 / midi.tempo_map.bpm_at(time.max(0.0));
+        let beat = quarter * 4.0 / f32::from(denominator.max(1));
+
+        let beats_per_bar = u32::from(numerator.max(1));
+
+        self.count_in = Some(CountIn {
+            clicks_left: u32::from(bars) * beats_per_bar,
+            beats_per_bar,
+            interval: Duration::from_secs_f32(beat / speed_multiplier),
+            next_click: Instant::now(),
+        });
+    }
+
+    pub fn is_counting_in(&self) -> bool {
+        self.count_in.is_some()
+    }
+
+    pub fn cancel_count_in(&mut self) {
+        self.count_in = None;
+    }
+
+    /// Returns true once count in is finished and playback should start
+    pub fn update_count_in(&mut self, output_manager: &mut OutputManager) -> bool {
+        let count_in = match &mut self.count_in {
+            Some(count_in) => count_in,
+            None => return false,
+        };
+
+        if Instant::now() < count_in.next_click {
+            return false;
+        }
+
+        if count_in.clicks_left == 0 {
+            self.count_in = None;
+            return true;
+        }
+
+        let accent = count_in.clicks_left % count_in.beats_per_bar == 0;
+        count_in.clicks_left -= 1;
+        count_in.next_click += count_in.interval;
+
+        self.click(output_manager, accent);
+
+        false
+    }
+}
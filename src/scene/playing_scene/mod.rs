@@ -1,4 +1,4 @@
-mod keyboard;
+pub mod keyboard;
 mod keyboard_pipeline;
 
 use keyboard::PianoKeyboard;
@@ -8,14 +8,31 @@ mod notes_pipeline;
 
 use notes::Notes;
 
+mod chase;
+mod metronome;
+use metronome::Metronome;
+
+mod tempo_trainer;
+use tempo_trainer::{Repetition, TempoTrainer};
+
+#[cfg(feature = "play_along")]
+mod play_along;
+#[cfg(feature = "play_along")]
+use play_along::PlayAlongControler;
+
 use super::{Scene, SceneEvent, SceneType};
 use lib_midi::MidiNote;
 
 use crate::{
+    device_watcher::ConnectionEvent,
+    input_manager::InputEvent,
+    keyboard_range::KeyRange,
     main_state::MainState,
+    practice_history::{self, PracticeRecord},
     rectangle_pipeline::{RectangleInstance, RectanglePipeline},
     target::Target,
-    time_manager::Timer,
+    time_manager::{Clock, MidiClockFollower, Timer},
+    track_mixer::{TrackMixer, TrackToggle},
     wgpu_jumpstart::Color,
 };
 
@@ -26,38 +43,88 @@ pub struct PlayingScene {
     notes: Notes,
     player: Player,
     rectangle_pipeline: RectanglePipeline,
+    tempo_trainer: Option<TempoTrainer>,
 
     text_toast: Option<Toast>,
+    /// Digits of target BPM that user is typing in
+    bpm_input: Option<String>,
+    /// Song times of tempo changes, marked on the progress bar
+    tempo_marks: Vec<f32>,
+    /// Devices in use that got unplugged
+    lost_devices: Vec<String>,
 
     keyboard_on: bool,  //editted for test
+
+    /// Unix timestamp of the scene start
+    session_start: u64,
+    /// Time spent practising (in seconds), pauses excluded
+    practice_time: f32,
+    last_update: std::time::Instant,
 }
 
 impl PlayingScene {
     pub fn new(target: &mut Target) -> Self {
-        let piano_keyboard = PianoKeyboard::new(target);
+        let keyboard_range = target
+            .state
+            .config
+            .keyboard_range
+            .resolve(target.state.midi_file.as_ref());
 
-        let mut notes = Notes::new(target, &piano_keyboard.all_keys);
+        let piano_keyboard = PianoKeyboard::new(target, keyboard_range);
 
-        let player = Player::new(&mut target.state);
+        let mut notes = Notes::new(target, keyboard_range, &piano_keyboard.all_keys);
+
+        let player = Player::new(&mut target.state, keyboard_range);
         notes.update(target, player.time);
 
-        Self {
+        let tempo_marks = {
+            let tempo_map = &target.state.midi_file.as_ref().unwrap().tempo_map;
+            tempo_map
+                .tempo_events()
+                .iter()
+                .filter(|e| e.time_in_units > 0.0)
+                .map(|e| tempo_map.pulses_to_secs(e.time_in_units))
+                .collect()
+        };
+
+        #[allow(unused_mut)]
+        let mut scene = Self {
             piano_keyboard,
             notes,
             player,
             rectangle_pipeline: RectanglePipeline::new(&target.gpu, &target.transform_uniform),
+            tempo_trainer: None,
 
             text_toast: None,
-            keyboard_on: true  //editted for test
+            bpm_input: None,
+            tempo_marks,
+            lost_devices: Vec::new(),
+            keyboard_on: true,  //editted for test
+
+            session_start: practice_history::unix_time(),
+            practice_time: 0.0,
+            last_update: std::time::Instant::now(),
+        };
+
+        if scene.player.timer.is_external() {
+            scene.toast("Following MIDI clock, count in and wait mode are off".into());
         }
-    }
 
-    fn speed_toast(&mut self, target: &mut Target) {
-        let s = format!(
-            "Speed: {}",
-            (target.state.config.speed_multiplier * 100.0).round() / 100.0
-        );
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &scene.player.play_along_controler {
+            if controler.is_computer_keyboard() {
+                scene.toast(
+                    "Computer keyboard plays notes on C M T B N P , . 2 3 5 6 7 9 0, \
+                     hold Ctrl or Alt to use their hotkeys"
+                        .into(),
+                );
+            }
+        }
 
+        scene
+    }
+
+    fn toast(&mut self, s: String) {
         self.text_toast = Some(Toast::new(move |target| {
             let text = vec![wgpu_glyph::Text::new(&s)
                 .with_color([1.0, 1.0, 1.0, 1.0])
@@ -76,28 +143,184 @@ impl PlayingScene {
         }));
     }
 
+    /// Tempo of the song at current time, speed included
+    fn current_bpm(&self, target: &Target) -> f32 {
+        let midi = target.state.midi_file.as_ref().unwrap();
+        midi.tempo_map.bpm_at(self.player.time.max(0.0)) * self.player.speed(&target.state)
+    }
+
+    fn speed_toast(&mut self, target: &mut Target) {
+        let s = format!(
+            "Speed: {} ({} BPM)",
+            (self.player.speed_multiplier(&target.state) * 100.0).round() / 100.0,
+            self.current_bpm(target).round()
+        );
+
+        self.toast(s);
+    }
+
+    /// Sets speed so that the song plays at `bpm` at current time
+    fn set_target_bpm(&mut self, target: &mut Target, bpm: f32) {
+        let midi = target.state.midi_file.as_ref().unwrap();
+        let song_bpm = midi.tempo_map.bpm_at(self.player.time.max(0.0));
+
+        if bpm > 0.0 {
+            self.player
+                .set_speed_multiplier(&mut target.state, bpm / song_bpm);
+        }
+
+        self.speed_toast(target);
+    }
+
+    /// Keys typed while target BPM is being entered, returns false if input is not active
+    fn bpm_input_event(&mut self, target: &mut Target, key: winit::event::VirtualKeyCode) -> bool {
+        use winit::event::VirtualKeyCode;
+
+        let input = match &mut self.bpm_input {
+            Some(input) => input,
+            None => return false,
+        };
+
+        let digit = match key {
+            VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Some('0'),
+            VirtualKeyCode::Key1 | VirtualKeyCode::Numpad1 => Some('1'),
+            VirtualKeyCode::Key2 | VirtualKeyCode::Numpad2 => Some('2'),
+            VirtualKeyCode::Key3 | VirtualKeyCode::Numpad3 => Some('3'),
+            VirtualKeyCode::Key4 | VirtualKeyCode::Numpad4 => Some('4'),
+            VirtualKeyCode::Key5 | VirtualKeyCode::Numpad5 => Some('5'),
+            VirtualKeyCode::Key6 | VirtualKeyCode::Numpad6 => Some('6'),
+            VirtualKeyCode::Key7 | VirtualKeyCode::Numpad7 => Some('7'),
+            VirtualKeyCode::Key8 | VirtualKeyCode::Numpad8 => Some('8'),
+            VirtualKeyCode::Key9 | VirtualKeyCode::Numpad9 => Some('9'),
+            _ => None,
+        };
+
+        if let Some(digit) = digit {
+            if input.len() < 3 {
+                input.push(digit);
+            }
+            return true;
+        }
+
+        match key {
+            VirtualKeyCode::Back => {
+                input.pop();
+            }
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                let bpm = input.parse::<f32>().ok();
+                self.bpm_input = None;
+
+                if let Some(bpm) = bpm {
+                    self.set_target_bpm(target, bpm);
+                }
+            }
+            VirtualKeyCode::Escape | VirtualKeyCode::B => {
+                self.bpm_input = None;
+            }
+            _ => {}
+        }
+
+        true
+    }
+
     fn offset_toast(&mut self, target: &mut Target) {
         let s = format!(
             "Offset: {}",
             (target.state.config.playback_offset * 100.0).round() / 100.0
         );
 
-        self.text_toast = Some(Toast::new(move |target| {
-            let text = vec![wgpu_glyph::Text::new(&s)
-                .with_color([1.0, 1.0, 1.0, 1.0])
-                .with_scale(20.0)];
+        self.toast(s);
+    }
 
-            target.text_renderer.queue_text(wgpu_glyph::Section {
-                text,
-                screen_position: (0.0, 20.0),
-                layout: wgpu_glyph::Layout::Wrap {
-                    line_breaker: Default::default(),
-                    h_align: wgpu_glyph::HorizontalAlign::Left,
-                    v_align: wgpu_glyph::VerticalAlign::Top,
-                },
-                ..Default::default()
-            });
-        }));
+    fn track_hotkey(&mut self, target: &mut Target, id: usize, toggle: TrackToggle) {
+        let is = match target.state.track_mixer.toggle(id, toggle) {
+            Some(is) => is,
+            None => return,
+        };
+
+        let what = match toggle {
+            TrackToggle::Mute => "Muted",
+            TrackToggle::Solo => "Solo",
+            TrackToggle::Hide => "Hidden",
+            TrackToggle::UserPlays => "I play",
+        };
+
+        let s = format!(
+            "{}: {} {}",
+            target.state.track_mixer.tracks()[id].name,
+            what,
+            if is { "on" } else { "off" }
+        );
+
+        if let TrackToggle::Hide = toggle {
+            self.notes.resize(target, &self.piano_keyboard.all_keys);
+        }
+
+        // Release notes of tracks that got muted, they will be picked up again if needed
+        self.player.clear(&mut target.state);
+
+        if let TrackToggle::UserPlays = toggle {
+            self.player.update_required_notes(&target.state);
+        }
+
+        self.toast(s);
+    }
+
+    /// Switches to the next keyboard size preset
+    fn next_keyboard_range(&mut self, target: &mut Target) {
+        let config = &mut target.state.config;
+        config.keyboard_range = config.keyboard_range.next_preset();
+
+        let range = config
+            .keyboard_range
+            .resolve(target.state.midi_file.as_ref());
+
+        self.player.set_keyboard_range(&target.state, range);
+        self.piano_keyboard.set_range(target, range);
+        self.notes
+            .set_range(target, range, &self.piano_keyboard.all_keys);
+
+        let s = format!("Keyboard: {}", target.state.config.keyboard_range);
+        self.toast(s);
+    }
+
+    /// Key pressed on the on-screen keyboard, play along judges it like any other input
+    fn screen_key_event(&mut self, target: &mut Target, event: InputEvent) {
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &self.player.play_along_controler {
+            controler.send_input(event);
+            return;
+        }
+
+        match event {
+            InputEvent::NoteOn { key, vel } => target.state.output_manager.note_on(0, key, vel),
+            InputEvent::NoteOff { key } => target.state.output_manager.note_off(0, key),
+        }
+    }
+
+    /// Pauses playback when the output or play along input gets unplugged
+    fn check_devices(&mut self, target: &mut Target) {
+        let output_event = target.state.output_manager.check_devices();
+        #[allow(unused_mut)]
+        let mut events: Vec<ConnectionEvent> = output_event.into_iter().collect();
+
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &mut self.player.play_along_controler {
+            events.extend(controler.check_devices());
+        }
+
+        for event in events {
+            match event {
+                ConnectionEvent::Lost(name) => {
+                    self.player.pause(&mut target.state);
+                    self.lost_devices.push(name);
+                }
+                ConnectionEvent::Restored(name) => {
+                    self.lost_devices.retain(|n| *n != name);
+                    self.toast(format!("{} reconnected, press Space to continue", name));
+                }
+            }
+        }
     }
 
     #[cfg(feature = "record")]
@@ -109,6 +332,50 @@ impl PlayingScene {
 impl Scene for PlayingScene {
     fn done(mut self: Box<Self>, target: &mut Target) {
         self.player.clear(&mut target.state);
+        target.state.output_manager.stop_midi_clock();
+        target.state.output_manager.panic();
+
+        #[allow(unused_mut)]
+        let mut score = None;
+
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &self.player.play_along_controler {
+            let report = controler.score().report();
+
+            if let Some(midi) = &target.state.midi_file {
+                controler
+                    .recorder()
+                    .save(&midi.tempo_map, target.state.midi_file_name.as_deref());
+            }
+
+            log::info!("Score: {}", report.total);
+            for (section, stats) in report.sections.iter() {
+                log::info!("Section {}: {}", section.name, stats);
+            }
+
+            target.state.last_score = Some(report.clone());
+            score = Some(report);
+        }
+
+        // Don't litter the history with sessions that were closed right away
+        if self.practice_time >= 1.0 {
+            if let Some(midi) = &target.state.midi_file {
+                let record = PracticeRecord {
+                    song_hash: midi.content_hash,
+                    song_name: target.state.midi_file_name.clone().unwrap_or_default(),
+                    date: self.session_start,
+                    duration: self.practice_time,
+                    loop_regions: self.player.used_loops.clone(),
+                    speed: self.player.speed_multiplier(&target.state),
+                    trainer_top_speed: self
+                        .tempo_trainer
+                        .as_ref()
+                        .and_then(|trainer| trainer.highest_speed()),
+                    score,
+                };
+                target.state.practice_history.add(record);
+            }
+        }
     }
 
     fn scene_type(&self) -> SceneType {
@@ -127,18 +394,75 @@ impl Scene for PlayingScene {
             (width, height)
         };
 
-        let notes_on = self.player.update(&mut target.state);
+        self.check_devices(target);
+
+        let mut notes_on = self.player.update(&mut target.state);
+
+        if self.player.take_repetition_finished() {
+            if let Some(trainer) = &mut self.tempo_trainer {
+                let accuracy = self.player.repetition_accuracy();
+                let speed = self.player.speed_multiplier(&target.state);
+                let repetition = trainer.repetition_finished(&target.state.config, speed, accuracy);
+                let n = trainer.repetitions();
+
+                if let Repetition::SpeedUp { speed } = repetition {
+                    self.player.speed_override = Some(speed);
+                }
+
+                let s = match repetition {
+                    Repetition::Failed { accuracy } => format!(
+                        "Repetition {}: {}%, try again",
+                        n,
+                        (accuracy * 100.0).round()
+                    ),
+                    Repetition::SpeedUp { speed } => format!(
+                        "Repetition {}: speed up to {}",
+                        n,
+                        (speed * 100.0).round() / 100.0
+                    ),
+                    Repetition::TargetReached { speed } => format!(
+                        "Repetition {}: target speed {} reached",
+                        n,
+                        (speed * 100.0).round() / 100.0
+                    ),
+                };
+                self.toast(s);
+            }
+
+            self.player.restart_repetition(&mut target.state);
+        }
+
+        {
+            let now = std::time::Instant::now();
+            if self.player.is_practising() {
+                self.practice_time += (now - self.last_update).as_secs_f32();
+            }
+            self.last_update = now;
+        }
 
         let size_x = window_w * self.player.percentage;
 
+        let mut rectangles = vec![RectangleInstance {
+            position: [0.0, 0.0],
+            size: [size_x, 5.0],
+            color: Color::from_rgba8(56, 145, 255, 1.0).into_linear_rgba(),
+        }];
+
+        for time in self.tempo_marks.iter() {
+            let p = (time - self.player.midi_first_note_start + 3.0)
+                / (self.player.midi_last_note_end + 3.0);
+
+            rectangles.push(RectangleInstance {
+                position: [window_w * p - 1.0, 0.0],
+                size: [2.0, 10.0],
+                color: Color::from_rgba8(255, 255, 255, 1.0).into_linear_rgba(),
+            });
+        }
+
         self.rectangle_pipeline.update_instance_buffer(
             &mut target.gpu.encoder,
             &target.gpu.device,
-            vec![RectangleInstance {
-                position: [0.0, 0.0],
-                size: [size_x, 5.0],
-                color: Color::from_rgba8(56, 145, 255, 1.0).into_linear_rgba(),
-            }],
+            rectangles,
         );
 
         let pos = &target.window.state.cursor_logical_position;
@@ -155,13 +479,20 @@ impl Scene for PlayingScene {
 
             if !self.player.rewind_controler.is_rewinding() {
                 self.player.start_rewind(RewindControler::Mouse {
-                    was_paused: self.player.timer.paused,
+                    was_paused: self.player.timer.is_paused(),
                 });
             }
         } else if let RewindControler::Mouse { .. } = self.player.rewind_controler {
             self.player.stop_rewind();
         }
 
+        let range = self.piano_keyboard.range();
+        for note in self.piano_keyboard.pointer_notes() {
+            if let Some(id) = range.id(*note) {
+                notes_on[id] = (true, 0);
+            }
+        }
+
         self.piano_keyboard.update_notes_state(target, notes_on);
         /*if !self.keyboard_on {
             self.notes.update(
@@ -179,6 +510,79 @@ impl Scene for PlayingScene {
             self.player.time + target.state.config.playback_offset,       // 판정 위치 바뀜 .. 판정 위치가 오락가락하는 이유는 time을 round하는 과정에서 발생하는 것이 아닐까(판정 위치를 결정하는 것이 아니라 노트의 위치를 결정하는 듯)
         );
 
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &self.player.play_along_controler {
+            let score = controler.score();
+            let total = score.total();
+
+            let s = format!(
+                "Hits: {}  Misses: {}  Wrong: {}  Streak: {}",
+                total.hits,
+                total.misses,
+                total.wrong_notes,
+                score.streak()
+            );
+
+            let text = vec![wgpu_glyph::Text::new(&s)
+                .with_color([1.0, 1.0, 1.0, 1.0])
+                .with_scale(20.0)];
+
+            target.text_renderer.queue_text(wgpu_glyph::Section {
+                text,
+                screen_position: (window_w, 20.0),
+                layout: wgpu_glyph::Layout::Wrap {
+                    line_breaker: Default::default(),
+                    h_align: wgpu_glyph::HorizontalAlign::Right,
+                    v_align: wgpu_glyph::VerticalAlign::Top,
+                },
+                ..Default::default()
+            });
+        }
+
+        {
+            let s = match &self.bpm_input {
+                Some(input) => format!("Target BPM: {}_", input),
+                None => format!("{} BPM", self.current_bpm(target).round()),
+            };
+
+            let text = vec![wgpu_glyph::Text::new(&s)
+                .with_color([1.0, 1.0, 1.0, 1.0])
+                .with_scale(20.0)];
+
+            target.text_renderer.queue_text(wgpu_glyph::Section {
+                text,
+                screen_position: (window_w / 2.0, 20.0),
+                layout: wgpu_glyph::Layout::Wrap {
+                    line_breaker: Default::default(),
+                    h_align: wgpu_glyph::HorizontalAlign::Center,
+                    v_align: wgpu_glyph::VerticalAlign::Top,
+                },
+                ..Default::default()
+            });
+        }
+
+        if !self.lost_devices.is_empty() {
+            let s = format!(
+                "Disconnected: {}\nPlayback is paused until the device is back",
+                self.lost_devices.join(", ")
+            );
+
+            let text = vec![wgpu_glyph::Text::new(&s)
+                .with_color([1.0, 1.0, 1.0, 1.0])
+                .with_scale(30.0)];
+
+            target.text_renderer.queue_text(wgpu_glyph::Section {
+                text,
+                screen_position: (window_w / 2.0, window_h / 3.0),
+                layout: wgpu_glyph::Layout::Wrap {
+                    line_breaker: Default::default(),
+                    h_align: wgpu_glyph::HorizontalAlign::Center,
+                    v_align: wgpu_glyph::VerticalAlign::Center,
+                },
+                ..Default::default()
+            });
+        }
+
         // Toasts
         {
             if let Some(mut toast) = self.text_toast.take() {
@@ -194,6 +598,9 @@ impl Scene for PlayingScene {
 
         if self.player.percentage >= 1.0 {
             //log::warn!("End");
+            if target.state.playlist.has_next() {
+                return SceneEvent::NextSong;
+            }
             return SceneEvent::GoBack
         } else {
             SceneEvent::None
@@ -235,6 +642,36 @@ impl Scene for PlayingScene {
         }
     }
     fn window_event(&mut self, target: &mut Target, event: &WindowEvent) -> SceneEvent {
+        if let winit::event::WindowEvent::KeyboardInput { input, .. } = &event {
+            // Computer keyboard input takes piano keys over their hotkeys,
+            // digits go to BPM entry while it is open
+            #[cfg(feature = "play_along")]
+            if let Some(controler) = &mut self.player.play_along_controler {
+                let modifiers = target.window.state.modifers_state;
+                let bpm_entry = self.bpm_input.is_some();
+                let pressed = input.state == winit::event::ElementState::Pressed;
+
+                if !(bpm_entry && pressed) && controler.key_event(input, modifiers) {
+                    return SceneEvent::None;
+                }
+            }
+
+            if let (winit::event::ElementState::Released, Some(key)) =
+                (input.state, input.virtual_keycode)
+            {
+                if self.bpm_input_event(target, key) {
+                    return SceneEvent::None;
+                }
+            }
+        }
+
+        // Keyboard can only be clicked while it is drawn
+        if !self.keyboard_on {
+            for e in self.piano_keyboard.pointer_event(target, event) {
+                self.screen_key_event(target, e);
+            }
+        }
+
         match &event {
             winit::event::WindowEvent::KeyboardInput { input, .. } => match input.virtual_keycode {
                 Some(winit::event::VirtualKeyCode::Escape) => {
@@ -258,7 +695,7 @@ impl Scene for PlayingScene {
                         if !self.player.rewind_controler.is_rewinding() {
                             self.player.start_rewind(RewindControler::Keyboard {
                                 speed,
-                                was_paused: self.player.timer.paused,
+                                was_paused: self.player.timer.is_paused(),
                             });
                         }
                     } else {
@@ -276,7 +713,7 @@ impl Scene for PlayingScene {
                         if !self.player.rewind_controler.is_rewinding() {
                             self.player.start_rewind(RewindControler::Keyboard {
                                 speed,
-                                was_paused: self.player.timer.paused,
+                                was_paused: self.player.timer.is_paused(),
                             });
                         }
                     } else {
@@ -285,30 +722,29 @@ impl Scene for PlayingScene {
                 }
                 Some(winit::event::VirtualKeyCode::Up) => {
                     if let winit::event::ElementState::Released = input.state {
-                        if target.window.state.modifers_state.shift() {
-                            target.state.config.speed_multiplier += 0.5;
+                        let step = if target.window.state.modifers_state.shift() {
+                            0.5
                         } else {
-                            target.state.config.speed_multiplier += 0.1;
-                        }
+                            0.1
+                        };
 
-                        self.player
-                            .set_percentage_time(&mut target.state, self.player.percentage);
+                        let speed = self.player.speed_multiplier(&target.state) + step;
+                        self.player.set_speed_multiplier(&mut target.state, speed);
 
                         self.speed_toast(target);
                     }
                 }
                 Some(winit::event::VirtualKeyCode::Down) => {
                     if let winit::event::ElementState::Released = input.state {
-                        let new = if target.window.state.modifers_state.shift() {
-                            target.state.config.speed_multiplier - 0.5
+                        let step = if target.window.state.modifers_state.shift() {
+                            0.5
                         } else {
-                            target.state.config.speed_multiplier - 0.1
+                            0.1
                         };
 
+                        let new = self.player.speed_multiplier(&target.state) - step;
                         if new > 0.0 {
-                            target.state.config.speed_multiplier = new;
-                            self.player
-                                .set_percentage_time(&mut target.state, self.player.percentage);
+                            self.player.set_speed_multiplier(&mut target.state, new);
                         }
 
                         self.speed_toast(target);
@@ -337,6 +773,142 @@ impl Scene for PlayingScene {
                         self.offset_toast(target);
                     }
                 }
+                Some(winit::event::VirtualKeyCode::LBracket) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        self.player.loop_start_mark = Some(self.player.time);
+
+                        let s = format!("Loop start: {:.1}s", self.player.time);
+                        self.toast(s);
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::RBracket) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        if let Some(start) = self.player.loop_start_mark {
+                            let end = self.player.time;
+                            if end > start {
+                                self.player.loop_region = Some((start, end));
+                                self.player.used_loops.push((start, end));
+                                self.player.restart_repetition(&mut target.state);
+
+                                let s = format!("Loop: {:.1}s - {:.1}s", start, end);
+                                self.toast(s);
+                            }
+                        }
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::P) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        target.state.output_manager.panic();
+                        self.toast("All notes off".into());
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::Backslash) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        self.player.loop_region = None;
+                        self.player.loop_start_mark = None;
+                        self.toast("Loop cleared".into());
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::T) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        if let Some(trainer) = self.tempo_trainer.take() {
+                            self.player.loop_song = false;
+
+                            // Back to the configured speed
+                            self.player.speed_override = None;
+                            self.player
+                                .set_percentage_time(&mut target.state, self.player.percentage);
+
+                            let s = match trainer.highest_speed() {
+                                Some(speed) => format!(
+                                    "Tempo trainer: off, highest speed {}",
+                                    (speed * 100.0).round() / 100.0
+                                ),
+                                None => "Tempo trainer: off".into(),
+                            };
+                            self.toast(s);
+                        } else {
+                            self.tempo_trainer = Some(TempoTrainer::default());
+                            self.player.speed_override =
+                                Some(TempoTrainer::start_speed(&target.state.config));
+                            self.player.loop_song = true;
+                            self.player.restart_repetition(&mut target.state);
+
+                            self.speed_toast(target);
+                        }
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::M) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        let config = &mut target.state.config;
+                        config.metronome = !config.metronome;
+
+                        let s = format!(
+                            "Metronome: {}",
+                            if config.metronome { "on" } else { "off" }
+                        );
+                        self.toast(s);
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::K) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        self.next_keyboard_range(target);
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::C) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        if self.player.timer.is_external() {
+                            self.toast("Count in is off while following MIDI clock".into());
+                        } else {
+                            let config = &mut target.state.config;
+                            config.count_in_bars = (config.count_in_bars + 1) % 3;
+
+                            let s = format!("Count in: {} bars", config.count_in_bars);
+                            self.toast(s);
+                        }
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::Comma)
+                | Some(winit::event::VirtualKeyCode::Period) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        let forward =
+                            input.virtual_keycode == Some(winit::event::VirtualKeyCode::Period);
+
+                        if target.window.state.modifers_state.shift() {
+                            match self.player.jump_marker(&mut target.state, forward) {
+                                Some(name) => self.toast(format!("Marker: {}", name)),
+                                None => self.toast("No more markers".into()),
+                            }
+                        } else {
+                            self.player.jump_bar(&mut target.state, forward);
+                        }
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::Home) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        self.player.set_percentage_time(&mut target.state, 0.0);
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::N) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        self.player.step(&mut target.state);
+                    }
+                }
+                Some(winit::event::VirtualKeyCode::B) => {
+                    if let winit::event::ElementState::Released = input.state {
+                        self.bpm_input = Some(String::new());
+                    }
+                }
+                Some(key) if track_hotkey_id(key).is_some() => {
+                    if let winit::event::ElementState::Released = input.state {
+                        let modifiers = target.window.state.modifers_state;
+                        if let (Some(id), Some(toggle)) =
+                            (track_hotkey_id(key), track_hotkey_toggle(modifiers))
+                        {
+                            self.track_hotkey(target, id, toggle);
+                        }
+                    }
+                }
                 Some(winit::event::VirtualKeyCode::Tab) => {
                     if let winit::event::ElementState::Released = input.state {
                         if target.window.state.modifers_state.shift() {
@@ -361,21 +933,48 @@ impl Scene for PlayingScene {
 
 use std::collections::HashMap;
 
+/// Jumping back within this time (in seconds) from a bar or marker skips to the one before it
+const JUMP_SLACK: f32 = 0.25;
+
 struct Player {
     midi_first_note_start: f32,
     midi_last_note_end: f32,
     active_notes: HashMap<usize, MidiNote>,
-    timer: Timer,
+    /// Index of first channel event (controllers, programs...) that was not sent yet
+    next_channel_event: usize,
+    /// Channel state and sounding notes have to be rebuilt before playback continues
+    chase_pending: bool,
+    /// Chord that was stepped to while paused
+    stepped_notes: Vec<MidiNote>,
+    keyboard_range: KeyRange,
+    timer: Box<dyn Clock>,
     percentage: f32,
     time: f32,
 
     rewind_controler: RewindControler,
     #[cfg(feature = "play_along")]
     play_along_controler: Option<PlayAlongControler>,
+
+    metronome: Metronome,
+    count_in_pending: bool,
+
+    /// A-B loop (start, end) in song seconds
+    loop_region: Option<(f32, f32)>,
+    /// Start of the loop that is waiting for its end
+    loop_start_mark: Option<f32>,
+    /// Every loop used during the session
+    used_loops: Vec<(f32, f32)>,
+    /// Restart whole song when it ends
+    loop_song: bool,
+    repetition_finished: bool,
+    /// Play along accuracy of the last finished repetition, `None` if nothing was judged
+    repetition_accuracy: Option<f32>,
+    /// Speed set by the tempo trainer, used instead of the configured one so it is not saved
+    speed_override: Option<f32>,
 }
 
 impl Player {
-    fn new(main_state: &mut MainState) -> Self {
+    fn new(main_state: &mut MainState, keyboard_range: KeyRange) -> Self {
         let midi_file = main_state.midi_file.as_ref().unwrap();
 
         let midi_first_note_start = if let Some(note) = midi_file.merged_track.notes.first() {
@@ -391,22 +990,65 @@ impl Player {
 
         #[cfg(feature = "play_along")]
         let play_along_controler = if main_state.config.play_along {
-            PlayAlongControler::new()
+            let track_mixer = &main_state.track_mixer;
+            let notes = midi_file
+                .merged_track
+                .notes
+                .iter()
+                .filter(|n| is_required_note(keyboard_range, track_mixer, n));
+
+            PlayAlongControler::new(
+                &main_state.config,
+                notes,
+                &midi_file.markers,
+                midi_first_note_start,
+                midi_last_note_end,
+            )
         } else {
             None
         };
 
+        let timer: Box<dyn Clock> = match &main_state.config.midi_clock_input {
+            Some(port_name) => match MidiClockFollower::new(
+                port_name,
+                midi_file.tempo_map.clone(),
+                midi_first_note_start - 3.0,
+            ) {
+                Some(clock) => Box::new(clock),
+                None => {
+                    log::warn!("MIDI clock input {} is not available", port_name);
+                    Box::new(Timer::new())
+                }
+            },
+            None => Box::new(Timer::new()),
+        };
+
         let mut player = Self {
             midi_first_note_start,
             midi_last_note_end,
             active_notes: HashMap::new(),
-            timer: Timer::new(),
+            next_channel_event: 0,
+            chase_pending: false,
+            stepped_notes: Vec::new(),
+            keyboard_range,
+            timer,
             percentage: 0.0,
             time: 0.0,
 
             rewind_controler: RewindControler::None,
             #[cfg(feature = "play_along")]
             play_along_controler,
+
+            metronome: Metronome::new(midi_file, midi_last_note_end),
+            count_in_pending: false,
+
+            loop_region: None,
+            loop_start_mark: None,
+            used_loops: Vec::new(),
+            loop_song: false,
+            repetition_finished: false,
+            repetition_accuracy: None,
+            speed_override: None,
         };
         player.update(main_state);
 
@@ -414,6 +1056,61 @@ impl Player {
     }
     fn start(&mut self) {
         self.timer.start();
+        self.count_in_pending = true;
+    }
+
+    /// External clock can not be held back for count in
+    fn count_in_enabled(&self, main_state: &MainState) -> bool {
+        main_state.config.count_in_bars > 0 && !self.timer.is_external()
+    }
+
+    fn start_count_in(&mut self, main_state: &mut MainState) {
+        let midi = main_state.midi_file.as_ref().unwrap();
+        let config = &main_state.config;
+
+        self.metronome.start_count_in(
+            midi,
+            config.count_in_bars,
+            self.speed_multiplier(main_state),
+            self.time,
+        );
+        self.timer.pause();
+    }
+
+    /// Play along expects only notes that fit on the keyboard
+    fn set_keyboard_range(&mut self, main_state: &MainState, keyboard_range: KeyRange) {
+        self.keyboard_range = keyboard_range;
+        self.update_required_notes(main_state);
+    }
+
+    /// Keyboard range or tracks played by the user changed, play along expects the new set of notes
+    #[allow(unused_variables)]
+    fn update_required_notes(&mut self, main_state: &MainState) {
+        #[cfg(feature = "play_along")]
+        if let (Some(controler), Some(midi)) = (
+            &mut self.play_along_controler,
+            main_state.midi_file.as_ref(),
+        ) {
+            let keyboard_range = self.keyboard_range;
+            let track_mixer = &main_state.track_mixer;
+            controler.set_notes(
+                &midi.merged_track.notes,
+                |n| is_required_note(keyboard_range, track_mixer, n),
+                self.time,
+            );
+        }
+    }
+
+    /// Playback runs or waits for the user to play
+    fn is_practising(&self) -> bool {
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &self.play_along_controler {
+            if controler.is_waiting() {
+                return true;
+            }
+        }
+
+        !self.timer.is_paused()
     }
 
     fn update(&mut self, main_state: &mut MainState) -> [(bool, usize); 88] {
@@ -422,90 +1119,419 @@ impl Player {
             self.set_percentage_time(main_state, p);
         }
 
+        let was_paused = self.timer.is_paused();
+        let last_time = self.time;
+
         self.timer.update();
-        let raw_time = self.timer.get_elapsed() / 1000.0 * main_state.config.speed_multiplier;
-        self.percentage = raw_time / (self.midi_last_note_end + 3.0);
-        self.time = raw_time + self.midi_first_note_start - 3.0;
+        self.update_time(main_state);
+
+        if self.timer.is_external() {
+            // Master stopped or jumped to another song position
+            if (!was_paused && self.timer.is_paused())
+                || self.time < last_time
+                || self.time - last_time > 0.5
+            {
+                self.seek(main_state, self.time);
+            }
+        }
+
+        if let Some((start, end)) = self.loop_bounds() {
+            if self.time >= end
+                && !self.rewind_controler.is_rewinding()
+                && !self.timer.is_external()
+            {
+                self.finish_repetition(start, end);
+                self.loop_back(main_state, start);
+                self.update_time(main_state);
+            }
+        }
+
+        if self.count_in_pending {
+            self.count_in_pending = false;
+            if self.count_in_enabled(main_state) {
+                self.start_count_in(main_state);
+            }
+        }
+
+        if self
+            .metronome
+            .update_count_in(&mut main_state.output_manager)
+        {
+            self.timer.resume();
+        }
+
+        if main_state.config.send_midi_clock {
+            let midi = main_state.midi_file.as_ref().unwrap();
+            let quarters = crate::midi_clock::song_quarters(&midi.tempo_map, self.time);
+
+            // Clock keeps running while wait mode waits, it just stops advancing,
+            // so only user pause and seek send transport messages
+            let playing = self.is_practising();
+            main_state
+                .output_manager
+                .update_midi_clock(playing, quarters);
+        }
+
+        if !self.timer.is_paused() {
+            let audio_time = self.time + main_state.config.output_latency * self.speed(main_state);
+            self.metronome.update(
+                &mut main_state.output_manager,
+                main_state.config.metronome,
+                audio_time,
+            );
+        }
 
         let mut notes_state: [(bool, usize); 88] = [(false, 0); 88];
 
         #[cfg(feature = "play_along")]
-        if let Some(controler) = &mut self.play_along_controler {
-            controler.update(main_state, &mut notes_state, &mut self.timer);
+        {
+            let speed = self.speed(main_state);
+            if let Some(controler) = &mut self.play_along_controler {
+                controler.update(
+                    main_state,
+                    &mut notes_state,
+                    self.keyboard_range,
+                    &mut *self.timer,
+                    self.time,
+                    speed,
+                );
+            }
         }
 
-        if self.timer.paused {
+        if self.timer.is_paused() {
+            let track_mixer = &main_state.track_mixer;
+            for n in self.stepped_notes.iter() {
+                if n.ch != 9 && n.ch != 8 && track_mixer.is_visible(n.track_id) {
+                    if let Some(id) = self.keyboard_range.id(n.note) {
+                        notes_state[id] = (true, n.track_id);
+                    }
+                }
+            }
+
             return notes_state;
         };
 
-        let filtered: Vec<&lib_midi::MidiNote> = main_state
-            .midi_file
-            .as_ref()
-            .unwrap()
-            .merged_track
-            .notes
+        let merged_track = &main_state.midi_file.as_ref().unwrap().merged_track;
+
+        let channel_events = &merged_track.channel_events;
+
+        if self.chase_pending {
+            self.chase_pending = false;
+
+            let time = self.time;
+            self.next_channel_event = channel_events
+                .iter()
+                .position(|e| e.time >= time)
+                .unwrap_or_else(|| channel_events.len());
+
+            chase::chase_channel_state(&mut main_state.output_manager, channel_events, time);
+
+            // Notes that started before playback continued
+            let sounding = merged_track
+                .notes
+                .iter()
+                .filter(|n| n.start < time && n.start + n.duration > time);
+            let config = &main_state.config;
+            let track_mixer = &main_state.track_mixer;
+            let keyboard_range = self.keyboard_range;
+            for n in sounding {
+                self.active_notes.insert(n.id, n.clone());
+
+                // User plays their own notes
+                let user_note =
+                    config.play_along && is_required_note(keyboard_range, track_mixer, n);
+
+                if config.chase_notes && !user_note && track_mixer.is_audible(n.track_id) {
+                    main_state.output_manager.note_on(n.ch, n.note, n.vel);
+                }
+            }
+        }
+
+        // Audio is sent ahead of the visuals to make up for the output latency
+        let audio_time = self.time + main_state.config.output_latency * self.speed(main_state);
+
+        while let Some(e) = channel_events.get(self.next_channel_event) {
+            if e.time > audio_time {
+                break;
+            }
+
+            main_state.output_manager.channel_message(e.ch, &e.message);
+            self.next_channel_event += 1;
+        }
+
+        let notes = &merged_track.notes;
+        let filtered: Vec<&lib_midi::MidiNote> = notes
             .iter()
-            .filter(|n| n.start <= self.time && n.start + n.duration + 0.5 > self.time)
+            .filter(|n| n.start <= audio_time && n.start + n.duration + 0.5 > self.time)
             .collect();
 
         let output_manager = &mut main_state.output_manager;
+        let track_mixer = &main_state.track_mixer;
+        let config = &main_state.config;
+        let keyboard_range = self.keyboard_range;
+
+        let is_required = |n: &MidiNote| is_required_note(keyboard_range, track_mixer, n);
 
         for n in filtered {
             use std::collections::hash_map::Entry;
 
-            if n.start + n.duration >= self.time {
-                if n.note >= 21 + 15 && n.note <= 108 - 12 - 3 && n.ch != 9 && n.ch != 8 {
-                    notes_state[n.note as usize - 21 - 15] = (true, n.track_id);
+            let audible = track_mixer.is_audible(n.track_id);
+
+            if n.start <= self.time
+                && n.start + n.duration >= self.time
+                && n.ch != 9
+                && n.ch != 8
+                && track_mixer.is_visible(n.track_id)
+            {
+                if let Some(id) = keyboard_range.id(n.note) {
+                    notes_state[id] = (true, n.track_id);
                 }
+            }
+
+            // Notes that user plays are due once they reach the keyboard
+            let due_time = if config.play_along && is_required(n) {
+                self.time
+            } else {
+                audio_time
+            };
 
+            if n.start > due_time {
+                continue;
+            }
+
+            if n.start + n.duration >= due_time {
                 if let Entry::Vacant(_e) = self.active_notes.entry(n.id) {
                     self.active_notes.insert(n.id, n.clone());
 
                     #[cfg(feature = "play_along")]
                     if let Some(controler) = &mut self.play_along_controler {
-                        if n.note >= 21 + 15 && n.note <= 108 - 12 - 3 && n.ch != 9 && n.ch != 8 {
-                            controler.require_note(&mut self.timer, &n);
-                            //log::warn!("required_notes: {:?}", controler.required_notes.lock().unwrap().len());
-                        } else {
-                            //if controler.waiting_for_note == false {
-                                //output_manager.note_on(n.ch, n.note, n.vel);
-                            //}
-                            /*let mut note_temp = [{0}; 3].to_vec();
-                            note_temp.push(n.ch);
-                            note_temp.push(n.note);
-                            note_temp.push(n.vel);
-                            controler.outrange_note_list.push(note_temp);   범위 밖 노트가 동시에 눌려야 하는 것이 여러 개인 경우... 아직 미완성(아마도 타입 에러 해결해야 함)*/
-                            controler.outrange_note_list.push(n.ch);
-                            controler.outrange_note_list.push(n.note);
-                            controler.outrange_note_list.push(n.vel);
-                            controler.outrange_note = true; 
-                            //output_manager.note_on(n.ch, n.note, n.vel);  원래 있던 부분
+                        if is_required(n) {
+                            // External clock keeps running, so there is no waiting for the user
+                            if !self.timer.is_external() {
+                                controler.require_note(
+                                    config,
+                                    &mut *self.timer,
+                                    notes,
+                                    n,
+                                    is_required,
+                                );
+                            }
+                        } else if audible {
+                            // Accompaniment and out of range notes are played by the output
+                            controler.queue_note(output_manager, n);
                         }
-                    } else {
+                    } else if audible {
                         output_manager.note_on(n.ch, n.note, n.vel);
                     }
-                    
 
                     #[cfg(not(feature = "play_along"))]
-                    output_manager.note_on(n.ch, n.note, n.vel);
+                    if audible {
+                        output_manager.note_on(n.ch, n.note, n.vel);
+                    }
                 }
-            } else if let Entry::Occupied(_e) = self.active_notes.entry(n.id) {  
-
-                if !main_state.config.play_along {
+            } else if let Entry::Occupied(_e) = self.active_notes.entry(n.id) {
+                if !config.play_along || !is_required(n) {
                     output_manager.note_off(n.ch, n.note);
-                }                                                                 
+                }
             }
         }
 
         notes_state
     }
 
+    /// External clock already runs in the tempo of the master
+    fn speed(&self, main_state: &MainState) -> f32 {
+        if self.timer.is_external() {
+            1.0
+        } else {
+            self.speed_multiplier(main_state)
+        }
+    }
+
+    /// Speed chosen by the user or the tempo trainer
+    fn speed_multiplier(&self, main_state: &MainState) -> f32 {
+        self.speed_override
+            .unwrap_or(main_state.config.speed_multiplier)
+    }
+
+    /// Changes the tempo trainer speed while it runs, configured speed otherwise
+    fn set_speed_multiplier(&mut self, main_state: &mut MainState, speed: f32) {
+        match &mut self.speed_override {
+            Some(s) => *s = speed,
+            None => main_state.config.speed_multiplier = speed,
+        }
+        self.set_percentage_time(main_state, self.percentage);
+    }
+
+    fn update_time(&mut self, main_state: &MainState) {
+        let raw_time = self.timer.get_elapsed() / 1000.0 * self.speed(main_state);
+        self.percentage = raw_time / (self.midi_last_note_end + 3.0);
+        self.time = raw_time + self.midi_first_note_start - 3.0;
+    }
+
+    fn loop_bounds(&self) -> Option<(f32, f32)> {
+        if self.loop_region.is_some() {
+            self.loop_region
+        } else if self.loop_song {
+            Some((self.midi_first_note_start - 3.0, self.midi_last_note_end))
+        } else {
+            None
+        }
+    }
+
+    fn take_repetition_finished(&mut self) -> bool {
+        std::mem::replace(&mut self.repetition_finished, false)
+    }
+
+    /// Judges the loop region before playback jumps back and its judgements are forgotten
+    #[allow(unused_variables)]
+    fn finish_repetition(&mut self, start: f32, end: f32) {
+        self.repetition_finished = true;
+        self.repetition_accuracy = None;
+
+        #[cfg(feature = "play_along")]
+        if let Some(controler) = &mut self.play_along_controler {
+            self.repetition_accuracy = controler.finish_repetition(start, end);
+        }
+    }
+
+    /// Accuracy of the last repetition, `None` if nothing was judged
+    fn repetition_accuracy(&self) -> Option<f32> {
+        self.repetition_accuracy
+    }
+
+    /// Jumps to the start of previous or next bar
+    fn jump_bar(&mut self, main_state: &mut MainState, forward: bool) {
+        let downbeats = self
+            .metronome
+            .beats()
+            .iter()
+            .filter(|b| b.is_downbeat())
+            .map(|b| b.time);
+
+        let time = if forward {
+            downbeats.find(|t| *t > self.time + 0.01)
+        } else {
+            downbeats.filter(|t| *t < self.time - JUMP_SLACK).last()
+        };
+
+        if let Some(time) = time {
+            self.set_song_time(main_state, time);
+        }
+    }
+
+    /// Jumps to previous or next marker, returns its name
+    fn jump_marker(&mut self, main_state: &mut MainState, forward: bool) -> Option<String> {
+        let markers = &main_state.midi_file.as_ref().unwrap().markers;
+
+        let marker = if forward {
+            markers.iter().find(|m| m.time > self.time + 0.01)
+        } else {
+            markers
+                .iter()
+                .rev()
+                .find(|m| m.time < self.time - JUMP_SLACK)
+        };
+
+        let (time, name) = marker.map(|m| (m.time, m.name.clone()))?;
+        self.set_song_time(main_state, time);
+
+        Some(name)
+    }
+
+    /// Steps to the next note onset (or chord) while paused and plays it
+    fn step(&mut self, main_state: &mut MainState) {
+        if !self.timer.is_paused() || self.metronome.is_counting_in() {
+            return;
+        }
+
+        // External clock can only be moved by its master
+        if self.timer.is_external() {
+            return;
+        }
+
+        let midi = main_state.midi_file.as_ref().unwrap();
+        let track_mixer = &main_state.track_mixer;
+
+        let onset = midi
+            .merged_track
+            .notes
+            .iter()
+            .filter(|n| track_mixer.is_visible(n.track_id))
+            .find(|n| n.start > self.time + 0.001)
+            .map(|n| n.start);
+
+        let onset = match onset {
+            Some(onset) => onset,
+            None => return,
+        };
+
+        self.set_song_time(main_state, onset);
+
+        let midi = main_state.midi_file.as_ref().unwrap();
+        let track_mixer = &main_state.track_mixer;
+        let chord_window = main_state.config.wait_mode_chord_window;
+
+        let chord = midi
+            .merged_track
+            .notes
+            .iter()
+            .skip_while(|n| n.start < onset)
+            .take_while(|n| n.start <= onset + chord_window)
+            .filter(|n| track_mixer.is_visible(n.track_id));
+
+        for n in chord {
+            if track_mixer.is_audible(n.track_id) {
+                main_state.output_manager.note_on(n.ch, n.note, n.vel);
+                self.active_notes.insert(n.id, n.clone());
+            }
+            self.stepped_notes.push(n.clone());
+        }
+    }
+
+    /// Jumps to the start of the loop (or the song) and starts a new repetition
+    fn restart_repetition(&mut self, main_state: &mut MainState) {
+        if let Some((start, _)) = self.loop_bounds() {
+            self.loop_back(main_state, start);
+        }
+    }
+
+    /// Jumps to the loop start without chasing, the loop replays its own channel events
+    /// and controllers should not be reset on every repetition
+    fn loop_back(&mut self, main_state: &mut MainState, start: f32) {
+        self.set_song_time(main_state, start);
+        self.chase_pending = false;
+    }
+
+    /// Stops playback (and count in) if it is running
+    fn pause(&mut self, main_state: &mut MainState) {
+        self.metronome.cancel_count_in();
+
+        if !self.timer.is_paused() {
+            self.clear(main_state);
+            self.timer.pause();
+        }
+    }
+
     fn pause_resume(&mut self, main_state: &mut MainState) {
         self.clear(main_state);
-        self.timer.pause_resume();
+
+        if self.timer.is_paused() {
+            // Notes released on pause start sounding again once playback resumes
+            self.chase_pending = true;
+        }
+
+        if self.metronome.is_counting_in() {
+            self.metronome.cancel_count_in();
+        } else if self.timer.is_paused() && self.count_in_enabled(main_state) {
+            self.start_count_in(main_state);
+        } else {
+            self.timer.pause_resume();
+        }
     }
 
     fn start_rewind(&mut self, controler: RewindControler) {
+        self.metronome.cancel_count_in();
         self.timer.pause();
         self.rewind_controler = controler;
     }
@@ -524,14 +1550,46 @@ impl Player {
     }
 
     fn set_time(&mut self, main_state: &mut MainState, time: f32) {
+        // External clock can only be moved by its master
+        if self.timer.is_external() {
+            return;
+        }
+
         self.timer.set_time(time * 1000.0);
+
+        let song_time = time * self.speed(main_state) + self.midi_first_note_start - 3.0;
+        self.seek(main_state, song_time);
+    }
+
+    /// Resets playback state after a jump to `song_time`
+    fn seek(&mut self, main_state: &mut MainState, song_time: f32) {
         self.clear(main_state);
+        self.chase_pending = true;
+        main_state.output_manager.seek_midi_clock();
+
+        let channel_events = &main_state
+            .midi_file
+            .as_ref()
+            .unwrap()
+            .merged_track
+            .channel_events;
+        self.next_channel_event = channel_events
+            .iter()
+            .position(|e| e.time >= song_time)
+            .unwrap_or_else(|| channel_events.len());
+    }
+
+    fn set_song_time(&mut self, main_state: &mut MainState, time: f32) {
+        self.set_time(
+            main_state,
+            (time - self.midi_first_note_start + 3.0) / self.speed(main_state),
+        );
     }
 
     fn set_percentage_time(&mut self, main_state: &mut MainState, p: f32) {
         self.set_time(
             main_state,
-            p * (self.midi_last_note_end + 3.0) / main_state.config.speed_multiplier,
+            p * (self.midi_last_note_end + 3.0) / self.speed(main_state),
         );
     }
 
@@ -540,6 +1598,7 @@ impl Player {
             main_state.output_manager.note_off(n.ch, n.note);
         }
         self.active_notes.clear();
+        self.stepped_notes.clear();
 
         #[cfg(feature = "play_along")]
         if let Some(controler) = &mut self.play_along_controler {
@@ -548,6 +1607,44 @@ impl Player {
     }
 }
 
+/// Track toggled by a number key, Key1 is the first track
+fn track_hotkey_id(key: winit::event::VirtualKeyCode) -> Option<usize> {
+    use winit::event::VirtualKeyCode;
+
+    match key {
+        VirtualKeyCode::Key1 => Some(0),
+        VirtualKeyCode::Key2 => Some(1),
+        VirtualKeyCode::Key3 => Some(2),
+        VirtualKeyCode::Key4 => Some(3),
+        VirtualKeyCode::Key5 => Some(4),
+        VirtualKeyCode::Key6 => Some(5),
+        VirtualKeyCode::Key7 => Some(6),
+        VirtualKeyCode::Key8 => Some(7),
+        VirtualKeyCode::Key9 => Some(8),
+        _ => None,
+    }
+}
+
+/// Ctrl: mute, Ctrl+Shift: solo, Alt: "I play this", Alt+Shift: hide.
+/// Plain and Shift digits are left to the computer keyboard input, which plays notes on them
+fn track_hotkey_toggle(modifiers: winit::event::ModifiersState) -> Option<TrackToggle> {
+    match (modifiers.ctrl(), modifiers.alt(), modifiers.shift()) {
+        (true, false, false) => Some(TrackToggle::Mute),
+        (true, false, true) => Some(TrackToggle::Solo),
+        (false, true, false) => Some(TrackToggle::UserPlays),
+        (false, true, true) => Some(TrackToggle::Hide),
+        _ => None,
+    }
+}
+
+/// Notes that user has to press in play along mode
+fn is_required_note(keyboard_range: KeyRange, track_mixer: &TrackMixer, n: &MidiNote) -> bool {
+    keyboard_range.contains(n.note)
+        && n.ch != 9
+        && n.ch != 8
+        && track_mixer.is_user_track(n.track_id)
+}
+
 enum RewindControler {
     Keyboard { speed: f32, was_paused: bool },
     Mouse { was_paused: bool },
@@ -562,169 +1659,6 @@ impl RewindControler {
     }
 }
 
-#[cfg(feature = "play_along")]
-use std::sync::{mpsc, Arc, Mutex};
-//use std::{thread, time};    //editted for test
-
-#[cfg(feature = "play_along")]
-struct PlayAlongControler {
-    _midi_in_conn: midir::MidiInputConnection<()>,
-    midi_in_rec: mpsc::Receiver<(bool, u8, u8)>,
-
-    input_pressed_keys: [bool; 88],
-    required_notes: Arc<Mutex<HashMap<u8, MidiNote>>>,
-    waiting_for_note: bool,
-    outrange_note: bool,
-    outrange_note_list: Vec<u8>,  //범위 밖 노트 한 개
-    //outrange_note_list: Vec<Vec<u8>>,  //범위 밖 노트 여러 개
-}
-
-#[cfg(feature = "play_along")]
-impl PlayAlongControler {
-    fn new() -> Option<Self> {
-        let input_pressed_keys = [false; 88];
-        let required_notes = Arc::new(Mutex::new(HashMap::new()));
-
-        let (tx, midi_in_rec) = mpsc::channel();
-
-        let _midi_in_conn = {
-            let midi_in = midir::MidiInput::new("Neothesia-in").unwrap();
-            let in_ports = midi_in.ports();
-
-            use std::io::{stdin, stdout, Write};
-
-            let in_port = match in_ports.len() {
-                0 => return None,
-                1 => {
-                    println!(
-                        "Choosing the only available input port: {}",
-                        midi_in.port_name(&in_ports[0]).unwrap()
-                    );
-                    &in_ports[0]
-                }
-                _ => {
-                    //println!("\nAvailable input ports:");                             //cargo run --features play_along일떄 (0,1) 묻지 말고 키보드를 input으로 하도록 하지만, 딱 우리 상황에만 맞춘 상태
-                    for (i, p) in in_ports.iter().enumerate() {
-                        println!("{}: {}", i, midi_in.port_name(p).unwrap());
-                    }
-                    //print!("Please select input port: ");
-                    stdout().flush().unwrap();
-                    /*let mut input = String::new();
-                    stdin().read_line(&mut input).unwrap();
-                    in_ports
-                        .get(input.trim().parse::<usize>().unwrap())
-                        .ok_or("invalid input port selected")
-                        .unwrap()*/
-                    in_ports
-                        .get("1".trim().parse::<usize>().unwrap())
-                        .ok_or("invalid input port selected")
-                        .unwrap()
-                }
-            };
-
-            let required_notes = required_notes.clone();
-
-            midi_in
-                .connect(
-                    in_port,
-                    "neothesia-read-input",
-                    move |_, message, _| {
-                        if message.len() == 3 {
-                            let note = message[1];
-                            if note >= 21 && note <= 108  {
-                                if message[0] == 128 || message[2] == 0 {
-                                    tx.send((false, message[1], message[2])).unwrap();
-                                } else if message[0] == 144 {
-                                    required_notes.lock().unwrap().remove(&note);
-                                    tx.send((true, message[1], message[2])).unwrap();
-                                }
-                            }
-                        }
-                    },
-                    (),
-                )
-                .unwrap()
-        };
-
-        Some(Self {
-            _midi_in_conn,
-            midi_in_rec,
-
-            input_pressed_keys,
-            required_notes,
-            waiting_for_note: false,
-            outrange_note: false,
-            outrange_note_list: [{0}; 3].to_vec(),  //범위 밖 노트 한개
-            //outrange_note_list: [[{0}; 3].to_vec(); 1].to_vec(), 범위 밖 노트 여러 개
-        })
-    }
-
-    fn update(
-        &mut self,
-        main_state: &mut MainState,
-        notes_state: &mut [(bool, usize); 88],
-        timer: &mut Timer,
-    ) {
-        for (id, is) in self.input_pressed_keys.iter().enumerate() {
-            notes_state[id] = (*is, 0);
-        }
-
-        if let Ok(event) = self.midi_in_rec.try_recv() {
-            if event.0 {
-                self.input_pressed_keys[event.1 as usize - 36] = true;
-                main_state.output_manager.note_on(0, event.1, event.2);
-                if self.required_notes.lock().unwrap().len() == 0 && self.outrange_note == true {  //outrnage_note는 범위 밖 노트가 존재하는지 판별
-                    /*let mut counter = 0;
-                    self.outrange_note_list.pop();
-                    for OutRangeNote in self.outrange_note_list {  //outrange_note_list는 범위 밖 노트의 ch, key, vel 순으로 저장한 벡터 속 벡터 타입으로, required_notes가 empty이면, 즉 동시에 눌려야 하는 노트가 다 눌리면 범위 밖 노트의 소리를 내기 위함
-                        main_state.output_manager.note_on(OutRangeNote[0], OutRangeNote[1], OutRangeNote[2]);
-                        counter += 1;
-                        //self.outrange_note_list.pop();
-                    }
-                    for counter in 0..counter {
-                        self.outrange_note_list.pop();    
-                    }  범위 밖 노트 여러 개가 동시에 눌려야 하는 경우... 아직 미완성*/
-                    self.outrange_note = false;
-                    main_state.output_manager.note_on(self.outrange_note_list[0], self.outrange_note_list[1], self.outrange_note_list[2]);
-                    self.outrange_note_list.pop();
-                    self.outrange_note_list.pop();
-                    self.outrange_note_list.pop();   //범위 밖 노트가 한번에 하나만 눌리면 되는 경우
-                }
-                
-            } else {
-                self.input_pressed_keys[event.1 as usize - 36] = false;
-                main_state.output_manager.note_off(0, event.1)
-            }
-        }
-        //log::warn!("required_notes: {:?}", self.required_notes.lock().unwrap().len());
-        
-        if self.required_notes.lock().unwrap().len() == 0 && self.waiting_for_note == true {
-            //main_state.output_manager.note_on(self.outrange_note_list[0], self.outrange_note_list[1], self.outrange_note_list[2]);
-            //self.outrange_note_list.pop();
-            self.waiting_for_note = false;
-            //self.outrange_note = false;
-            timer.resume();
-        }  
-    }
-
-    fn require_note(&mut self, timer: &mut Timer, n: &MidiNote) {
-        //if n.note >= 21  + 15 && n.note <= 108 - 12 && n.ch != 9 && n.ch != 8{
-            //let time_check = time::Instant::now();   //editted for test
-            self.required_notes
-                .lock()
-                .unwrap()
-                .insert(n.note , n.clone());
-            self.waiting_for_note = true;
-            //log::warn!("time elapse: {:?}", time_check); //editted for test
-            timer.pause();
-        //}
-    }
-
-    fn clear(&mut self) {
-        self.required_notes.lock().unwrap().clear();
-    }
-}
-
 struct Toast {
     start_time: std::time::Instant,
     inner_draw: Box<dyn Fn(&mut Target)>,
@@ -1,4 +1,5 @@
 use super::notes_pipeline::{NoteInstance, NotesPipeline};
+use crate::keyboard_range::KeyRange;
 use crate::target::Target;
 use crate::wgpu_jumpstart::Color;
 use crate::TransformUniform;
@@ -6,15 +7,28 @@ use crate::Uniform;
 
 pub struct Notes {
     notes_pipeline: NotesPipeline,
+    range: KeyRange,
 }
 
 impl Notes {
-    pub fn new(target: &mut Target, keys: &[super::keyboard::Key]) -> Self {
+    pub fn new(target: &mut Target, range: KeyRange, keys: &[super::keyboard::Key]) -> Self {
         let notes_pipeline = NotesPipeline::new(target, target.state.midi_file.as_ref().unwrap());
-        let mut notes = Self { notes_pipeline };
+        let mut notes = Self {
+            notes_pipeline,
+            range,
+        };
         notes.resize(target, keys);
         notes
     }
+    pub fn set_range(
+        &mut self,
+        target: &mut Target,
+        range: KeyRange,
+        keys: &[super::keyboard::Key],
+    ) {
+        self.range = range;
+        self.resize(target, keys);
+    }
     pub fn resize(&mut self, target: &mut Target, keys: &[super::keyboard::Key]) {
         let midi = &target.state.midi_file.as_ref().unwrap();
 
@@ -25,10 +39,19 @@ impl Notes {
 
         let mut instances = Vec::new();
 
-        let mut longer_than_88 = false;
+        let mut out_of_range = false;
         for note in midi.merged_track.notes.iter() {
-            if note.note >= 21 + 15 && note.note <= 108 - 12 - 3 && note.ch != 9 && note.ch != 8 /*&& note.ch != 7 && note.ch != 6 */{
-                let key = &keys[note.note as usize - 21 - 15];
+            if !target.state.track_mixer.is_visible(note.track_id) {
+                continue;
+            }
+
+            // Drums
+            if note.ch == 9 || note.ch == 8 {
+                continue;
+            }
+
+            if let Some(id) = self.range.id(note.note) {
+                let key = &keys[id];
                 let ar = window_w / window_h;
 
                 let color_schema = &target.state.config.color_schema;
@@ -51,12 +74,16 @@ impl Notes {
                 });
                 
             } else {
-                longer_than_88 = true;
+                out_of_range = true;
             }
         }
 
-        if longer_than_88 {
-            log::warn!("Midi Wider Than 88 Keys!");
+        if out_of_range {
+            log::warn!(
+                "Midi Wider Than Keyboard Range ({} - {})!",
+                self.range.first(),
+                self.range.last()
+            );
         }
 
         self.notes_pipeline
@@ -0,0 +1,290 @@
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
+
+use lib_midi::{Marker, MidiNote};
+
+use crate::{
+    config::Config,
+    device_watcher::ConnectionEvent,
+    input_manager::{InputEvent, InputManager},
+    keyboard_range::KeyRange,
+    main_state::MainState,
+    output_manager::OutputManager,
+    performance_recorder::PerformanceRecorder,
+    scoring::{EarlyPresses, ScoreKeeper, Section},
+    time_manager::Clock,
+};
+
+/// Length of score sections in seconds
+const SECTION_LENGTH: f32 = 30.0;
+
+pub struct PlayAlongControler {
+    input: InputManager,
+
+    input_pressed_keys: [bool; 128],
+
+    /// Notes of the chord that user has to press before playback resumes
+    required_notes: HashMap<u8, MidiNote>,
+    /// Ids of notes that were already grouped into a chord or pressed early
+    handled_notes: HashSet<usize>,
+    /// Index of the first note of the last grouped chord, next chord is looked up from here
+    chord_cursor: usize,
+    /// Keys pressed ahead of time
+    early_presses: EarlyPresses,
+    /// Accompaniment and out of range notes that wait for required chord
+    queued_notes: Vec<MidiNote>,
+
+    waiting_for_note: bool,
+    wait_start: Option<Instant>,
+
+    score: ScoreKeeper,
+    recorder: PerformanceRecorder,
+    last_time: f32,
+}
+
+impl PlayAlongControler {
+    /// `notes` are the notes that user is expected to play,
+    /// score is split into sections by `markers` if song has any
+    pub fn new<'a>(
+        config: &Config,
+        notes: impl Iterator<Item = &'a MidiNote>,
+        markers: &[Marker],
+        song_start: f32,
+        song_end: f32,
+    ) -> Option<Self> {
+        let input = InputManager::new(config.midi_input.as_deref())?;
+
+        let sections = if markers.is_empty() {
+            Section::split(song_start, song_end, SECTION_LENGTH)
+        } else {
+            Section::from_markers(markers, song_start, song_end)
+        };
+
+        Some(Self {
+            input,
+
+            input_pressed_keys: [false; 128],
+
+            required_notes: HashMap::new(),
+            handled_notes: HashSet::new(),
+            chord_cursor: 0,
+            early_presses: EarlyPresses::default(),
+            queued_notes: Vec::new(),
+
+            waiting_for_note: false,
+            wait_start: None,
+
+            score: ScoreKeeper::new(notes, sections, config.score_hit_window),
+            recorder: PerformanceRecorder::new(),
+            last_time: song_start,
+        })
+    }
+
+    pub fn is_computer_keyboard(&self) -> bool {
+        self.input.is_computer_keyboard()
+    }
+
+    /// Returns true if the key was used by computer keyboard input
+    pub fn key_event(
+        &mut self,
+        input: &winit::event::KeyboardInput,
+        modifiers: winit::event::ModifiersState,
+    ) -> bool {
+        self.input.key_event(input, modifiers)
+    }
+
+    /// Treats the event as if it came from the input device
+    pub fn send_input(&self, event: InputEvent) {
+        self.input.send(event);
+    }
+
+    /// Reconnects the input once it is plugged back in
+    pub fn check_devices(&mut self) -> Option<ConnectionEvent> {
+        self.input.check_devices()
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        self.waiting_for_note
+    }
+
+    pub fn score(&self) -> &ScoreKeeper {
+        &self.score
+    }
+
+    /// Misses notes of `start..end` that were not played before playback loops back,
+    /// returns accuracy of the region or `None` if nothing was judged
+    pub fn finish_repetition(&mut self, start: f32, end: f32) -> Option<f32> {
+        self.score.miss_before(end);
+
+        let stats = self.score.stats_between(start, end);
+        if stats.hits + stats.misses + stats.wrong_notes > 0 {
+            Some(stats.accuracy())
+        } else {
+            None
+        }
+    }
+
+    /// Notes user is expected to play changed, notes that are no longer required stop the wait
+    pub fn set_notes(
+        &mut self,
+        notes: &[MidiNote],
+        is_required: impl Fn(&MidiNote) -> bool,
+        time: f32,
+    ) {
+        self.score
+            .set_notes(notes.iter().filter(|n| is_required(n)), time);
+        self.required_notes.retain(|_, n| is_required(n));
+    }
+
+    pub fn recorder(&self) -> &PerformanceRecorder {
+        &self.recorder
+    }
+
+    pub fn update(
+        &mut self,
+        main_state: &mut MainState,
+        notes_state: &mut [(bool, usize); 88],
+        keyboard_range: KeyRange,
+        timer: &mut dyn Clock,
+        time: f32,
+        speed: f32,
+    ) {
+        let early_margin = main_state.config.wait_mode_early_margin;
+
+        if time < self.last_time {
+            self.score.rewind(time);
+            self.recorder.rewind(time);
+        }
+        self.last_time = time;
+
+        // Song time stands still while we wait, so time spent waiting is added to judge late presses
+        let judge_time = match self.wait_start {
+            Some(start) => time + start.elapsed().as_secs_f32() * speed,
+            None => time,
+        };
+
+        // Key was pressed earlier than we got to know about it
+        let input_latency = main_state.config.input_latency * speed;
+        let judge_time = judge_time - input_latency;
+        // Recording stays aligned with the song, so presses made while waiting land on their notes
+        let record_time = time - input_latency;
+
+        while let Some((_, event)) = self.input.try_recv() {
+            match event {
+                InputEvent::NoteOn { key, vel } => {
+                    self.input_pressed_keys[key as usize % 128] = true;
+                    main_state.output_manager.note_on(0, key, vel);
+                    self.recorder.note_on(record_time, key, vel);
+
+                    // Note we waited for is judged by its distance to the press, not by the time window
+                    match self.required_notes.remove(&key) {
+                        Some(note) => self.score.note_on_waited(&note, judge_time),
+                        None => {
+                            // Press that hits nothing may still be an early press of a note we will wait for
+                            let judged = self.score.try_hit(key, judge_time);
+                            self.early_presses.push(key, time - input_latency, judged);
+                        }
+                    }
+                }
+                InputEvent::NoteOff { key } => {
+                    self.input_pressed_keys[key as usize % 128] = false;
+                    main_state.output_manager.note_off(0, key);
+                    self.score.note_off(key, judge_time);
+                    self.recorder.note_off(record_time, key);
+                }
+            }
+        }
+
+        // Song time, so notes we are waiting for are not missed while the wait lasts
+        self.score.update(time - input_latency);
+
+        // Presses older than early margin can no longer satisfy upcoming notes
+        self.early_presses
+            .expire(&mut self.score, time - input_latency, early_margin);
+
+        for (key, is) in self.input_pressed_keys.iter().enumerate() {
+            if let Some(id) = keyboard_range.id(key as u8) {
+                notes_state[id] = (*is, 0);
+            }
+        }
+
+        if self.required_notes.is_empty() && self.waiting_for_note {
+            self.waiting_for_note = false;
+            self.wait_start = None;
+
+            for n in self.queued_notes.drain(..) {
+                main_state.output_manager.note_on(n.ch, n.note, n.vel);
+            }
+
+            timer.resume();
+        }
+    }
+
+    /// Groups every required note that starts within chord window of `n`
+    /// and pauses playback until all of them are pressed
+    pub fn require_note(
+        &mut self,
+        config: &Config,
+        timer: &mut dyn Clock,
+        notes: &[MidiNote],
+        n: &MidiNote,
+        is_required: impl Fn(&MidiNote) -> bool,
+    ) {
+        if self.handled_notes.contains(&n.id) {
+            return;
+        }
+
+        let chord_window = config.wait_mode_chord_window;
+        let early_margin = config.wait_mode_early_margin;
+
+        // Notes are sorted, cursor only moves back after a seek
+        self.chord_cursor = self.chord_cursor.min(notes.len());
+        while self.chord_cursor > 0 && notes[self.chord_cursor - 1].start >= n.start {
+            self.chord_cursor -= 1;
+        }
+        while self.chord_cursor < notes.len() && notes[self.chord_cursor].start < n.start {
+            self.chord_cursor += 1;
+        }
+
+        let chord = notes[self.chord_cursor..]
+            .iter()
+            .take_while(|c| c.start <= n.start + chord_window)
+            .filter(|c| is_required(c));
+
+        for c in chord.chain(std::iter::once(n)) {
+            if !self.handled_notes.insert(c.id) {
+                continue;
+            }
+
+            if !self.early_presses.take(&mut self.score, c, early_margin) {
+                self.required_notes.insert(c.note, c.clone());
+            }
+        }
+
+        if !self.required_notes.is_empty() {
+            if !self.waiting_for_note {
+                self.wait_start = Some(Instant::now());
+            }
+            self.waiting_for_note = true;
+            timer.pause();
+        }
+    }
+
+    /// Plays the note right away, or holds it until required chord is satisfied
+    pub fn queue_note(&mut self, output_manager: &mut OutputManager, n: &MidiNote) {
+        if self.waiting_for_note {
+            self.queued_notes.push(n.clone());
+        } else {
+            output_manager.note_on(n.ch, n.note, n.vel);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.required_notes.clear();
+        self.handled_notes.clear();
+        self.early_presses.clear();
+        self.queued_notes.clear();
+        self.waiting_for_note = false;
+        self.wait_start = None;
+    }
+}
@@ -0,0 +1,64 @@
+use crate::config::Config;
+
+/// Raises speed after every successful repetition of a loop (or the whole song).
+/// Speed is kept by the player, so the trainer does not change the configured one
+#[derive(Default)]
+pub struct TempoTrainer {
+    repetitions: usize,
+    /// Highest speed at which a repetition met the score threshold
+    highest_speed: Option<f32>,
+}
+
+pub enum Repetition {
+    /// Repetition did not meet the threshold, speed stays the same
+    Failed {
+        accuracy: f32,
+    },
+    SpeedUp {
+        speed: f32,
+    },
+    TargetReached {
+        speed: f32,
+    },
+}
+
+impl TempoTrainer {
+    pub fn start_speed(config: &Config) -> f32 {
+        config.tempo_trainer_start_speed
+    }
+
+    pub fn highest_speed(&self) -> Option<f32> {
+        self.highest_speed
+    }
+
+    pub fn repetitions(&self) -> usize {
+        self.repetitions
+    }
+
+    /// `accuracy` is `None` when nothing was judged (eg. play along is off),
+    /// such repetition always counts as successful
+    pub fn repetition_finished(
+        &mut self,
+        config: &Config,
+        speed: f32,
+        accuracy: Option<f32>,
+    ) -> Repetition {
+        self.repetitions += 1;
+
+        if let Some(accuracy) = accuracy {
+            if accuracy < config.tempo_trainer_threshold {
+                return Repetition::Failed { accuracy };
+            }
+        }
+
+        self.highest_speed = Some(self.highest_speed.unwrap_or(0.0).max(speed));
+
+        let target = config.tempo_trainer_target_speed;
+        if speed >= target {
+            Repetition::TargetReached { speed }
+        } else {
+            let speed = (speed + config.tempo_trainer_step).min(target);
+            Repetition::SpeedUp { speed }
+        }
+    }
+}
@@ -0,0 +1,767 @@
+use std::collections::HashMap;
+
+use lib_midi::MidiNote;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Grade {
+    S,
+    A,
+    B,
+    C,
+    D,
+    F,
+}
+
+impl std::fmt::Display for Grade {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScoreStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub wrong_notes: usize,
+
+    /// Hits that were pressed before the note start
+    pub early: usize,
+    /// Hits that were pressed after the note start
+    pub late: usize,
+    /// Sum of absolute hit offsets in milliseconds
+    pub offset_sum_ms: f32,
+
+    /// Sum of held duration accuracy (0.0 ..= 1.0) of released hits
+    pub held_accuracy_sum: f32,
+    pub held_count: usize,
+
+    pub longest_streak: usize,
+}
+
+impl ScoreStats {
+    fn hit(&mut self, offset_ms: f32, streak: usize) {
+        self.hits += 1;
+        self.offset_sum_ms += offset_ms.abs();
+
+        if offset_ms < 0.0 {
+            self.early += 1;
+        } else if offset_ms > 0.0 {
+            self.late += 1;
+        }
+
+        self.longest_streak = self.longest_streak.max(streak);
+    }
+
+    fn release(&mut self, accuracy: f32) {
+        self.held_accuracy_sum += accuracy;
+        self.held_count += 1;
+    }
+
+    /// Hits compared to everything that was judged, wrong notes included
+    pub fn accuracy(&self) -> f32 {
+        let total = self.hits + self.misses + self.wrong_notes;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f32 / total as f32
+        }
+    }
+
+    pub fn average_offset_ms(&self) -> f32 {
+        if self.hits == 0 {
+            0.0
+        } else {
+            self.offset_sum_ms / self.hits as f32
+        }
+    }
+
+    pub fn held_accuracy(&self) -> f32 {
+        if self.held_count == 0 {
+            0.0
+        } else {
+            self.held_accuracy_sum / self.held_count as f32
+        }
+    }
+
+    pub fn grade(&self) -> Grade {
+        // Timing and held duration weight less than pressing the right keys
+        let score = self.accuracy() * 0.8 + self.held_accuracy() * 0.2;
+
+        if score >= 0.95 {
+            Grade::S
+        } else if score >= 0.85 {
+            Grade::A
+        } else if score >= 0.7 {
+            Grade::B
+        } else if score >= 0.55 {
+            Grade::C
+        } else if score >= 0.4 {
+            Grade::D
+        } else {
+            Grade::F
+        }
+    }
+}
+
+impl std::fmt::Display for ScoreStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}% (hits: {}, misses: {}, wrong: {}, avg offset: {}ms, held: {}%, streak: {})",
+            self.grade(),
+            (self.accuracy() * 100.0).round(),
+            self.hits,
+            self.misses,
+            self.wrong_notes,
+            self.average_offset_ms().round(),
+            (self.held_accuracy() * 100.0).round(),
+            self.longest_streak,
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Section {
+    pub name: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Section {
+    /// Splits song into sections of fixed length
+    pub fn split(start: f32, end: f32, length: f32) -> Vec<Section> {
+        let mut sections = Vec::new();
+
+        let mut section_start = start;
+        while section_start < end {
+            let section_end = (section_start + length).min(end);
+            sections.push(Section {
+                name: format!(
+                    "{}:{:02}",
+                    (section_start.max(0.0) / 60.0) as u32,
+                    (section_start.max(0.0) % 60.0) as u32
+                ),
+                start: section_start,
+                end: section_end,
+            });
+            section_start = section_end;
+        }
+
+        sections
+    }
+
+    /// One section per marker, time before the first marker is a section of its own
+    pub fn from_markers(markers: &[lib_midi::Marker], start: f32, end: f32) -> Vec<Section> {
+        let mut sections = Vec::new();
+
+        let first = markers.first().map(|m| m.time).unwrap_or(end);
+        if first > start {
+            sections.push(Section {
+                name: "Intro".into(),
+                start,
+                end: first.min(end),
+            });
+        }
+
+        for (id, marker) in markers.iter().enumerate() {
+            let section_end = markers.get(id + 1).map(|m| m.time).unwrap_or(end);
+            if section_end > marker.time {
+                sections.push(Section {
+                    name: marker.name.clone(),
+                    start: marker.time,
+                    end: section_end,
+                });
+            }
+        }
+
+        sections
+    }
+
+    fn contains(&self, time: f32) -> bool {
+        time >= self.start && time < self.end
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreReport {
+    pub total: ScoreStats,
+    pub sections: Vec<(Section, ScoreStats)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Judgement {
+    Pending,
+    /// Press offset from note start, held duration accuracy once the key is released
+    Hit {
+        offset_ms: f32,
+        held: Option<f32>,
+    },
+    Missed,
+}
+
+struct ExpectedNote {
+    note: MidiNote,
+    judgement: Judgement,
+}
+
+/// Compares user input with expected notes.
+/// Stats are computed from judgements, so notes judged again after a rewind count only once
+pub struct ScoreKeeper {
+    expected: Vec<ExpectedNote>,
+    /// Index of first expected note that is still pending
+    first_pending: usize,
+    /// Pressed key -> (expected note id, press time)
+    held: HashMap<u8, (usize, f32)>,
+    /// Times of presses that matched no note, sorted
+    wrong_notes: Vec<f32>,
+
+    /// How far (in seconds) a press can be from note start to count as hit
+    hit_window: f32,
+    streak: usize,
+
+    sections: Vec<Section>,
+}
+
+impl ScoreKeeper {
+    pub fn new<'a>(
+        notes: impl Iterator<Item = &'a MidiNote>,
+        sections: Vec<Section>,
+        hit_window: f32,
+    ) -> Self {
+        let expected = notes
+            .map(|note| ExpectedNote {
+                note: note.clone(),
+                judgement: Judgement::Pending,
+            })
+            .collect();
+
+        Self {
+            expected,
+            first_pending: 0,
+            held: HashMap::new(),
+            wrong_notes: Vec::new(),
+
+            hit_window,
+            streak: 0,
+
+            sections,
+        }
+    }
+
+    pub fn streak(&self) -> usize {
+        self.streak
+    }
+
+    pub fn total(&self) -> ScoreStats {
+        self.stats(|_| true)
+    }
+
+    /// Stats of notes that start in `start..end`
+    pub fn stats_between(&self, start: f32, end: f32) -> ScoreStats {
+        self.stats(|time| time >= start && time < end)
+    }
+
+    fn stats(&self, filter: impl Fn(f32) -> bool) -> ScoreStats {
+        let mut stats = ScoreStats::default();
+        let mut streak = 0;
+
+        let mut wrong_notes = self
+            .wrong_notes
+            .iter()
+            .copied()
+            .filter(|time| filter(*time))
+            .peekable();
+
+        for expected in self.expected.iter().filter(|e| filter(e.note.start)) {
+            // Wrong notes break the streak in the order they were played
+            while wrong_notes
+                .next_if(|time| *time < expected.note.start)
+                .is_some()
+            {
+                stats.wrong_notes += 1;
+                streak = 0;
+            }
+
+            match expected.judgement {
+                Judgement::Hit { offset_ms, held } => {
+                    streak += 1;
+                    stats.hit(offset_ms, streak);
+                    if let Some(accuracy) = held {
+                        stats.release(accuracy);
+                    }
+                }
+                Judgement::Missed => {
+                    stats.misses += 1;
+                    streak = 0;
+                }
+                Judgement::Pending => {}
+            }
+        }
+        stats.wrong_notes += wrong_notes.count();
+
+        stats
+    }
+
+    pub fn note_on(&mut self, key: u8, time: f32) {
+        if !self.try_hit(key, time) {
+            self.wrong_note(time);
+        }
+    }
+
+    /// Hits the closest pending note of `key` within hit window, returns false if there is none
+    pub fn try_hit(&mut self, key: u8, time: f32) -> bool {
+        let hit_window = self.hit_window;
+
+        let candidate = self.expected[self.first_pending..]
+            .iter()
+            .enumerate()
+            .take_while(|(_, e)| e.note.start <= time + hit_window)
+            .filter(|(_, e)| {
+                e.note.note == key
+                    && matches!(e.judgement, Judgement::Pending)
+                    && (e.note.start - time).abs() <= hit_window
+            })
+            .min_by(|(_, a), (_, b)| {
+                let a = (a.note.start - time).abs();
+                let b = (b.note.start - time).abs();
+                a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .map(|(id, _)| id + self.first_pending);
+
+        match candidate {
+            Some(id) => {
+                self.hit(id, key, time);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Press of a note that playback waited for, judged by its distance to the note start
+    /// no matter how long the wait took
+    pub fn note_on_waited(&mut self, note: &MidiNote, time: f32) {
+        let id = self.expected[self.first_pending..]
+            .iter()
+            .position(|e| e.note.id == note.id && matches!(e.judgement, Judgement::Pending))
+            .map(|id| id + self.first_pending);
+
+        match id {
+            Some(id) => self.hit(id, note.note, time),
+            None => self.note_on(note.note, time),
+        }
+    }
+
+    fn hit(&mut self, id: usize, key: u8, time: f32) {
+        let offset_ms = (time - self.expected[id].note.start) * 1000.0;
+        self.expected[id].judgement = Judgement::Hit {
+            offset_ms,
+            held: None,
+        };
+
+        self.streak += 1;
+        self.held.insert(key, (id, time));
+    }
+
+    pub fn wrong_note(&mut self, time: f32) {
+        self.streak = 0;
+
+        let id = self
+            .wrong_notes
+            .iter()
+            .rposition(|t| *t <= time)
+            .map(|id| id + 1)
+            .unwrap_or(0);
+        self.wrong_notes.insert(id, time);
+    }
+
+    pub fn note_off(&mut self, key: u8, time: f32) {
+        if let Some((id, press_time)) = self.held.remove(&key) {
+            let expected = &mut self.expected[id];
+            let duration = expected.note.duration;
+
+            let held = time - press_time;
+            let accuracy = if duration > 0.0 {
+                (1.0 - (held - duration).abs() / duration).max(0.0)
+            } else {
+                1.0
+            };
+
+            if let Judgement::Hit { held, .. } = &mut expected.judgement {
+                *held = Some(accuracy);
+            }
+        }
+    }
+
+    /// Marks notes that can no longer be hit as missed
+    pub fn update(&mut self, time: f32) {
+        while let Some(expected) = self.expected.get_mut(self.first_pending) {
+            if expected.note.start + self.hit_window >= time {
+                break;
+            }
+
+            if let Judgement::Pending = expected.judgement {
+                expected.judgement = Judgement::Missed;
+                self.streak = 0;
+            }
+
+            self.first_pending += 1;
+        }
+    }
+
+    /// Marks every pending note that starts before `time` as missed,
+    /// even if it could still be hit, used when playback jumps away
+    pub fn miss_before(&mut self, time: f32) {
+        self.update(time + self.hit_window);
+    }
+
+    /// Replaces expected notes, used when the set of notes user plays changes mid song.
+    /// Judged notes keep their judgement, new notes are expected only if they start after `time`
+    pub fn set_notes<'a>(&mut self, notes: impl Iterator<Item = &'a MidiNote>, time: f32) {
+        let previous = &self.expected;
+        let held_ids: HashMap<u8, (usize, f32)> = self
+            .held
+            .drain()
+            .map(|(key, (id, press_time))| (key, (previous[id].note.id, press_time)))
+            .collect();
+
+        let mut previous: HashMap<usize, ExpectedNote> = self
+            .expected
+            .drain(..)
+            .map(|expected| (expected.note.id, expected))
+            .collect();
+
+        let mut expected = Vec::new();
+        for note in notes {
+            match previous.remove(&note.id) {
+                Some(e) => expected.push(e),
+                None if note.start >= time => expected.push(ExpectedNote {
+                    note: note.clone(),
+                    judgement: Judgement::Pending,
+                }),
+                None => {}
+            }
+        }
+        // Notes that were already played still count
+        expected.extend(
+            previous
+                .into_iter()
+                .map(|(_, e)| e)
+                .filter(|e| !matches!(e.judgement, Judgement::Pending)),
+        );
+        expected.sort_by(|a, b| {
+            a.note
+                .start
+                .partial_cmp(&b.note.start)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        self.expected = expected;
+        self.first_pending = self
+            .expected
+            .iter()
+            .position(|e| matches!(e.judgement, Judgement::Pending))
+            .unwrap_or_else(|| self.expected.len());
+
+        for (key, (note_id, press_time)) in held_ids {
+            if let Some(id) = self.expected.iter().position(|e| e.note.id == note_id) {
+                self.held.insert(key, (id, press_time));
+            }
+        }
+    }
+
+    /// Forgets judgements of notes after `time`, used when user seeks
+    pub fn rewind(&mut self, time: f32) {
+        self.held.clear();
+        self.streak = 0;
+
+        while self.first_pending > 0 && self.expected[self.first_pending - 1].note.start >= time {
+            self.first_pending -= 1;
+        }
+        for expected in self.expected[self.first_pending..].iter_mut() {
+            if expected.note.start >= time {
+                expected.judgement = Judgement::Pending;
+            }
+        }
+
+        self.wrong_notes.retain(|t| *t < time);
+    }
+
+    pub fn report(&self) -> ScoreReport {
+        ScoreReport {
+            total: self.total(),
+            sections: self
+                .sections
+                .iter()
+                .map(|section| (section.clone(), self.stats(|time| section.contains(time))))
+                .collect(),
+        }
+    }
+}
+
+struct EarlyPress {
+    key: u8,
+    /// Song time of the press
+    time: f32,
+    /// Press already hit a note within hit window
+    judged: bool,
+}
+
+/// Presses that may satisfy a note wait mode is about to wait for.
+/// Press that hit nothing becomes a wrong note only once it is too old to be an early press
+#[derive(Default)]
+pub struct EarlyPresses {
+    presses: Vec<EarlyPress>,
+}
+
+impl EarlyPresses {
+    pub fn push(&mut self, key: u8, time: f32, judged: bool) {
+        self.presses.push(EarlyPress { key, time, judged });
+    }
+
+    /// Takes a press of `note` made at most `early_margin` before it,
+    /// press that was not judged yet counts as a hit of the note
+    pub fn take(&mut self, score: &mut ScoreKeeper, note: &MidiNote, early_margin: f32) -> bool {
+        let id = self
+            .presses
+            .iter()
+            .position(|p| p.key == note.note && p.time >= note.start - early_margin);
+
+        match id {
+            Some(id) => {
+                let press = self.presses.remove(id);
+                if !press.judged {
+                    score.note_on_waited(note, press.time);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops presses older than `early_margin`, the ones that hit nothing are wrong notes
+    pub fn expire(&mut self, score: &mut ScoreKeeper, time: f32, early_margin: f32) {
+        for press in self.presses.iter() {
+            if !press.judged && time - press.time > early_margin {
+                score.wrong_note(press.time);
+            }
+        }
+        self.presses.retain(|p| time - p.time <= early_margin);
+    }
+
+    pub fn clear(&mut self) {
+        self.presses.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(id: usize, key: u8, start: f32) -> MidiNote {
+        MidiNote {
+            start,
+            duration: 0.5,
+            note: key,
+            vel: 100,
+            ch: 0,
+            track_id: 0,
+            id,
+        }
+    }
+
+    fn keeper(notes: &[MidiNote]) -> ScoreKeeper {
+        ScoreKeeper::new(notes.iter(), Vec::new(), 0.1)
+    }
+
+    #[test]
+    fn hits_misses_and_wrong_notes() {
+        let notes = [note(0, 60, 1.0), note(1, 62, 2.0), note(2, 64, 3.0)];
+        let mut score = keeper(&notes);
+
+        score.note_on(60, 0.95);
+        score.note_on(61, 1.5);
+        score.update(2.5);
+        score.note_on(64, 3.05);
+        score.update(4.0);
+
+        let total = score.total();
+        assert_eq!(total.hits, 2);
+        assert_eq!(total.misses, 1);
+        assert_eq!(total.wrong_notes, 1);
+        assert_eq!(total.early, 1);
+        assert_eq!(total.late, 1);
+        assert_eq!(total.longest_streak, 1);
+        assert_eq!(score.streak(), 1);
+    }
+
+    #[test]
+    fn rewind_does_not_count_notes_twice() {
+        let notes = [note(0, 60, 1.0), note(1, 62, 2.0)];
+        let mut score = keeper(&notes);
+
+        score.note_on(60, 1.0);
+        score.note_on(61, 1.5);
+        score.update(3.0);
+
+        score.rewind(1.2);
+        score.note_on(62, 2.0);
+        score.update(3.0);
+
+        let total = score.total();
+        assert_eq!(total.hits, 2);
+        assert_eq!(total.misses, 0);
+        assert_eq!(total.wrong_notes, 0);
+        assert_eq!(total.longest_streak, 2);
+    }
+
+    #[test]
+    fn waited_note_is_not_missed_while_waiting() {
+        let notes = [note(0, 60, 1.0)];
+        let mut score = keeper(&notes);
+
+        // Song time stands still at note start while playback waits
+        score.update(1.0);
+        score.note_on_waited(&notes[0], 3.0);
+        score.update(1.0);
+        score.update(2.0);
+
+        let total = score.total();
+        assert_eq!(total.hits, 1);
+        assert_eq!(total.misses, 0);
+        assert_eq!(total.wrong_notes, 0);
+        assert_eq!(total.late, 1);
+    }
+
+    #[test]
+    fn miss_before_loop_end() {
+        let notes = [note(0, 60, 1.0), note(1, 62, 1.95), note(2, 64, 2.5)];
+        let mut score = keeper(&notes);
+
+        score.note_on(60, 1.0);
+        score.update(2.0);
+        score.miss_before(2.0);
+
+        let stats = score.stats_between(0.0, 2.0);
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+        assert_eq!(score.total().misses, 1);
+    }
+
+    #[test]
+    fn release_accuracy() {
+        let notes = [note(0, 60, 1.0)];
+        let mut score = keeper(&notes);
+
+        score.note_on(60, 1.0);
+        score.note_off(60, 1.25);
+
+        let total = score.total();
+        assert_eq!(total.held_count, 1);
+        assert!((total.held_accuracy() - 0.5).abs() < 0.001);
+    }
+
+    #[test]
+    fn set_notes_keeps_judgements() {
+        let notes = [
+            note(0, 60, 1.0),
+            note(1, 30, 1.5),
+            note(2, 62, 2.0),
+            note(3, 31, 2.5),
+            note(4, 64, 3.0),
+        ];
+        let mut score = ScoreKeeper::new(notes.iter().filter(|n| n.note >= 60), Vec::new(), 0.1);
+
+        score.note_on(60, 1.0);
+        score.update(2.2);
+
+        // Range now covers every note, low note at 1.5 was never shown so it is not missed
+        score.set_notes(notes.iter(), 2.2);
+        score.note_on(31, 2.5);
+        score.note_off(31, 3.0);
+        score.update(4.0);
+
+        let total = score.total();
+        assert_eq!(total.hits, 2);
+        assert_eq!(total.misses, 2);
+        assert_eq!(total.held_accuracy(), 1.0);
+
+        // Range shrinks again, judged low note still counts, pending ones are dropped
+        score.rewind(2.8);
+        score.set_notes(notes.iter().filter(|n| n.note >= 60), 2.8);
+        score.update(4.0);
+
+        let total = score.total();
+        assert_eq!(total.hits, 2);
+        assert_eq!(total.misses, 2);
+    }
+
+    #[test]
+    fn early_press_taken_by_wait_is_a_hit() {
+        let notes = [note(0, 60, 1.0), note(1, 62, 2.0)];
+        let mut score = keeper(&notes);
+        let mut early = EarlyPresses::default();
+
+        // Outside of hit window, but within early margin
+        assert!(!score.try_hit(60, 0.82));
+        early.push(60, 0.82, false);
+        early.expire(&mut score, 0.9, 0.2);
+
+        // Wait mode reaches the note
+        assert!(early.take(&mut score, &notes[0], 0.2));
+        score.update(1.0);
+        early.expire(&mut score, 1.0, 0.2);
+
+        let total = score.total();
+        assert_eq!(total.hits, 1);
+        assert_eq!(total.misses, 0);
+        assert_eq!(total.wrong_notes, 0);
+        assert_eq!(total.early, 1);
+    }
+
+    #[test]
+    fn early_press_nobody_takes_is_a_wrong_note() {
+        let notes = [note(0, 60, 1.0)];
+        let mut score = keeper(&notes);
+        let mut early = EarlyPresses::default();
+
+        // Press within hit window is judged right away and not judged again
+        assert!(score.try_hit(60, 0.95));
+        early.push(60, 0.95, true);
+        assert!(early.take(&mut score, &notes[0], 0.2));
+
+        // Press of a key that is not coming
+        assert!(!score.try_hit(64, 1.1));
+        early.push(64, 1.1, false);
+        early.expire(&mut score, 1.2, 0.2);
+        assert_eq!(score.total().wrong_notes, 0);
+        early.expire(&mut score, 1.4, 0.2);
+
+        let total = score.total();
+        assert_eq!(total.hits, 1);
+        assert_eq!(total.wrong_notes, 1);
+        assert!(!early.take(&mut score, &notes[0], 0.2));
+    }
+
+    #[test]
+    fn stats_are_split_by_sections() {
+        let notes = [note(0, 60, 1.0), note(1, 62, 3.0)];
+        let sections = Section::split(0.0, 4.0, 2.0);
+        let mut score = ScoreKeeper::new(notes.iter(), sections, 0.1);
+
+        score.note_on(60, 1.0);
+        score.note_on(70, 2.5);
+        score.update(4.0);
+
+        let report = score.report();
+        assert_eq!(report.sections.len(), 2);
+
+        let (_, first) = &report.sections[0];
+        assert_eq!((first.hits, first.misses, first.wrong_notes), (1, 0, 0));
+
+        let (_, second) = &report.sections[1];
+        assert_eq!((second.hits, second.misses, second.wrong_notes), (0, 1, 1));
+
+        let between = score.stats_between(2.0, 4.0);
+        assert_eq!((between.hits, between.misses), (0, 1));
+    }
+}
@@ -0,0 +1,160 @@
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use lib_midi::TempoMap;
+
+use super::Clock;
+use crate::device_watcher::find_port;
+use crate::midi_clock::{
+    CONTINUE, PPQN, SONG_POSITION_POINTER, START, STOP, TICKS_PER_SPP, TIMING_CLOCK,
+};
+
+/// Follows MIDI clock and transport messages of an external device (eg. DAW)
+pub struct MidiClockFollower {
+    _midi_in_conn: midir::MidiInputConnection<()>,
+    midi_in_rec: mpsc::Receiver<(Instant, Vec<u8>)>,
+
+    tempo_map: TempoMap,
+    /// Song time (in seconds) at which playback time starts
+    start_offset: f32,
+
+    running: bool,
+    /// Clock ticks received since the song start
+    ticks: u64,
+    last_tick: Option<Instant>,
+    /// Smoothed time between ticks, used to interpolate between them
+    tick_interval: Duration,
+}
+
+impl MidiClockFollower {
+    /// `port_name` is matched against names of available MIDI inputs, see `find_port`
+    pub fn new(port_name: &str, tempo_map: TempoMap, start_offset: f32) -> Option<Self> {
+        let (tx, midi_in_rec) = mpsc::channel();
+
+        let mut midi_in = midir::MidiInput::new("Neothesia-clock-in").ok()?;
+        midi_in.ignore(midir::Ignore::None);
+
+        let ports = midi_in.ports();
+        let names: Vec<String> = ports
+            .iter()
+            .map(|p| midi_in.port_name(p).unwrap_or_default())
+            .collect();
+        let in_port = &ports[find_port(&names, port_name)?];
+
+        log::info!(
+            "Following MIDI clock of: {}",
+            midi_in.port_name(in_port).unwrap_or_default()
+        );
+
+        let _midi_in_conn = midi_in
+            .connect(
+                in_port,
+                "neothesia-clock-read",
+                move |_, message, _| {
+                    if let Some(status) = message.first() {
+                        if *status >= 0xF0 {
+                            tx.send((Instant::now(), message.to_vec())).ok();
+                        }
+                    }
+                },
+                (),
+            )
+            .ok()?;
+
+        Some(Self {
+            _midi_in_conn,
+            midi_in_rec,
+
+            tempo_map,
+            start_offset,
+
+            running: false,
+            ticks: 0,
+            last_tick: None,
+            // 120 bpm
+            tick_interval: Duration::from_secs_f32(0.5 / PPQN),
+        })
+    }
+
+    fn handle_message(&mut self, stamp: Instant, message: &[u8]) {
+        match message {
+            [TIMING_CLOCK] => {
+                if !self.running {
+                    return;
+                }
+
+                if let Some(last_tick) = self.last_tick {
+                    let interval = stamp.saturating_duration_since(last_tick);
+                    self.tick_interval = self.tick_interval.mul_f32(0.9) + interval.mul_f32(0.1);
+                }
+                self.last_tick = Some(stamp);
+                self.ticks += 1;
+            }
+            [START] => {
+                self.ticks = 0;
+                self.last_tick = Some(stamp);
+                self.running = true;
+            }
+            [CONTINUE] => {
+                self.last_tick = Some(stamp);
+                self.running = true;
+            }
+            [STOP] => {
+                self.running = false;
+            }
+            [SONG_POSITION_POINTER, lsb, msb] => {
+                let position = u64::from(*lsb) | (u64::from(*msb) << 7);
+                self.ticks = position * TICKS_PER_SPP;
+                self.last_tick = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Position in quarter notes since the song start
+    fn quarters(&self) -> f32 {
+        let mut ticks = self.ticks as f32;
+
+        if self.running {
+            if let Some(last_tick) = self.last_tick {
+                let since = last_tick.elapsed().as_secs_f32();
+                let interval = self.tick_interval.as_secs_f32();
+                if interval > 0.0 {
+                    // Never run ahead of the next tick
+                    ticks += (since / interval).min(1.0);
+                }
+            }
+        }
+
+        ticks / PPQN
+    }
+}
+
+impl Clock for MidiClockFollower {
+    // Transport is controlled by the master, user can't start, pause or seek
+    fn start(&mut self) {}
+    fn set_time(&mut self, _time: f32) {}
+    fn pause(&mut self) {}
+    fn resume(&mut self) {}
+    fn pause_resume(&mut self) {}
+
+    fn update(&mut self) {
+        while let Ok((stamp, message)) = self.midi_in_rec.try_recv() {
+            self.handle_message(stamp, &message);
+        }
+    }
+
+    fn get_elapsed(&self) -> f32 {
+        let pulses = self.quarters() * self.tempo_map.u_per_quarter_note();
+        let time = self.tempo_map.pulses_to_secs(pulses);
+        (time - self.start_offset) * 1000.0
+    }
+
+    fn is_paused(&self) -> bool {
+        !self.running
+    }
+
+    fn is_external(&self) -> bool {
+        true
+    }
+}
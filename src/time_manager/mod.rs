@@ -1,5 +1,26 @@
+mod midi_clock;
+pub use midi_clock::MidiClockFollower;
+
 use std::time::Instant;
 
+/// Source of playback time
+pub trait Clock {
+    fn start(&mut self);
+    fn update(&mut self);
+    /// Playback time in ms
+    fn get_elapsed(&self) -> f32;
+    fn set_time(&mut self, time: f32);
+    fn is_paused(&self) -> bool;
+    fn pause(&mut self);
+    fn resume(&mut self);
+    fn pause_resume(&mut self);
+    /// Clock is driven by an external device,
+    /// it already follows tempo of the master so speed multiplier does not apply
+    fn is_external(&self) -> bool {
+        false
+    }
+}
+
 pub struct Timer {
     pub time_elapsed: u128,
     last_time: Instant,
@@ -37,7 +58,7 @@ impl Timer {
     }
 
     pub fn set_time(&mut self, time: f32) {
-        if time > 0.0 {
+        if time >= 0.0 {
             self.time_elapsed = (time * 1_000_000.0).round() as u128;
         }
     }
@@ -59,6 +80,33 @@ impl Timer {
     }
 }
 
+impl Clock for Timer {
+    fn start(&mut self) {
+        Timer::start(self)
+    }
+    fn update(&mut self) {
+        Timer::update(self)
+    }
+    fn get_elapsed(&self) -> f32 {
+        Timer::get_elapsed(self)
+    }
+    fn set_time(&mut self, time: f32) {
+        Timer::set_time(self, time)
+    }
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+    fn pause(&mut self) {
+        Timer::pause(self)
+    }
+    fn resume(&mut self) {
+        Timer::resume(self)
+    }
+    fn pause_resume(&mut self) {
+        Timer::pause_resume(self)
+    }
+}
+
 pub struct Fps {
     fps: i32,
     fps_counter: i32,
@@ -0,0 +1,206 @@
+#[derive(Debug, Clone, Copy)]
+pub enum TrackToggle {
+    Mute,
+    Solo,
+    Hide,
+    UserPlays,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackState {
+    pub track_id: usize,
+    pub name: String,
+    pub notes_count: usize,
+
+    pub muted: bool,
+    pub solo: bool,
+    pub hidden: bool,
+    /// Track is played by the user in play along mode
+    pub user_plays: bool,
+}
+
+impl TrackState {
+    pub fn flag_mut(&mut self, toggle: TrackToggle) -> &mut bool {
+        match toggle {
+            TrackToggle::Mute => &mut self.muted,
+            TrackToggle::Solo => &mut self.solo,
+            TrackToggle::Hide => &mut self.hidden,
+            TrackToggle::UserPlays => &mut self.user_plays,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct TrackMixer {
+    tracks: Vec<TrackState>,
+}
+
+impl TrackMixer {
+    pub fn new(midi: Option<&lib_midi::Midi>) -> Self {
+        let tracks = if let Some(midi) = midi {
+            midi.tracks
+                .iter()
+                .filter(|trk| !trk.notes.is_empty())
+                .map(|trk| TrackState {
+                    track_id: trk.track_id,
+                    name: match &trk.name {
+                        Some(name) if !name.is_empty() => name.clone(),
+                        _ => format!("Track {}", trk.track_id),
+                    },
+                    notes_count: trk.notes.len(),
+
+                    muted: false,
+                    solo: false,
+                    hidden: false,
+                    user_plays: false,
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        Self { tracks }
+    }
+
+    pub fn tracks(&self) -> &[TrackState] {
+        &self.tracks
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut TrackState> {
+        self.tracks.get_mut(id)
+    }
+
+    /// Flips the setting of track at `id` list position, returns its new value
+    pub fn toggle(&mut self, id: usize, toggle: TrackToggle) -> Option<bool> {
+        let flag = self.tracks.get_mut(id)?.flag_mut(toggle);
+        *flag = !*flag;
+        Some(*flag)
+    }
+
+    fn track(&self, track_id: usize) -> Option<&TrackState> {
+        self.tracks.iter().find(|t| t.track_id == track_id)
+    }
+
+    /// Should notes of this track be sent to the output
+    pub fn is_audible(&self, track_id: usize) -> bool {
+        let any_solo = self.tracks.iter().any(|t| t.solo);
+
+        match self.track(track_id) {
+            Some(t) if any_solo => t.solo,
+            Some(t) => !t.muted,
+            None => !any_solo,
+        }
+    }
+
+    /// Should notes of this track be drawn
+    pub fn is_visible(&self, track_id: usize) -> bool {
+        self.track(track_id).map(|t| !t.hidden).unwrap_or(true)
+    }
+
+    /// Should play along wait for notes of this track.
+    /// When user did not assign any track, every track is treated as user track
+    pub fn is_user_track(&self, track_id: usize) -> bool {
+        if self.tracks.iter().any(|t| t.user_plays) {
+            self.track(track_id).map(|t| t.user_plays).unwrap_or(false)
+        } else {
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mixer(count: usize) -> TrackMixer {
+        TrackMixer {
+            tracks: (0..count)
+                .map(|id| TrackState {
+                    // Tracks without notes are left out, so ids have gaps
+                    track_id: id * 2,
+                    name: format!("Track {}", id * 2),
+                    notes_count: 10,
+
+                    muted: false,
+                    solo: false,
+                    hidden: false,
+                    user_plays: false,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn hidden_tracks_are_not_visible() {
+        let mut mixer = mixer(2);
+        mixer.get_mut(1).unwrap().hidden = true;
+
+        assert!(mixer.is_visible(0));
+        assert!(!mixer.is_visible(2));
+        // Unknown tracks are drawn
+        assert!(mixer.is_visible(7));
+    }
+
+    #[test]
+    fn every_track_is_user_track_until_one_is_picked() {
+        let mut mixer = mixer(3);
+        assert!(mixer.is_user_track(0));
+        assert!(mixer.is_user_track(4));
+
+        mixer.get_mut(1).unwrap().user_plays = true;
+        assert!(!mixer.is_user_track(0));
+        assert!(mixer.is_user_track(2));
+        assert!(!mixer.is_user_track(4));
+        assert!(!mixer.is_user_track(7));
+    }
+
+    #[test]
+    fn muted_tracks_are_silent() {
+        let mut mixer = mixer(2);
+        assert_eq!(mixer.toggle(0, TrackToggle::Mute), Some(true));
+
+        assert!(!mixer.is_audible(0));
+        assert!(mixer.is_audible(2));
+        assert!(mixer.is_audible(7));
+
+        assert_eq!(mixer.toggle(0, TrackToggle::Mute), Some(false));
+        assert!(mixer.is_audible(0));
+    }
+
+    #[test]
+    fn solo_silences_every_other_track() {
+        let mut mixer = mixer(3);
+        mixer.toggle(1, TrackToggle::Solo);
+        // Solo wins over mute
+        mixer.toggle(1, TrackToggle::Mute);
+
+        assert!(!mixer.is_audible(0));
+        assert!(mixer.is_audible(2));
+        assert!(!mixer.is_audible(4));
+        assert!(!mixer.is_audible(7));
+
+        mixer.toggle(2, TrackToggle::Solo);
+        assert!(mixer.is_audible(4));
+
+        mixer.toggle(1, TrackToggle::Solo);
+        mixer.toggle(2, TrackToggle::Solo);
+        assert!(mixer.is_audible(0));
+        assert!(!mixer.is_audible(2));
+    }
+
+    #[test]
+    fn toggle_out_of_range_does_nothing() {
+        let mut mixer = mixer(1);
+        assert_eq!(mixer.toggle(1, TrackToggle::Solo), None);
+        assert!(mixer.is_audible(0));
+    }
+
+    #[test]
+    fn get_mut_uses_list_position() {
+        let mut mixer = mixer(2);
+
+        assert_eq!(mixer.get_mut(1).map(|t| t.track_id), Some(2));
+        assert!(mixer.get_mut(2).is_none());
+        assert_eq!(mixer.tracks().len(), 2);
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    output_manager::{MidiEvent, SynthEvent, SynthRenderer},
+    track_mixer::TrackMixer,
+};
+
+const USAGE: &str = "Usage: neothesia --render-wav <out.wav> <song.mid> [--speed <multiplier>] \
+                     [--transpose <semitones>] [--mute <track,...>] [--soundfont <file.sf2>] \
+                     [--sample-rate <hz>]";
+
+const DEFAULT_SAMPLE_RATE: u32 = 44100;
+const CHANNELS: u16 = 2;
+
+/// Frames rendered at once, events are queued right before the block they fall into
+const BLOCK_FRAMES: usize = 1024;
+/// Time (in seconds) rendered after the last note, so it can ring out
+const TAIL: f32 = 2.0;
+
+pub struct RenderOptions {
+    pub soundfont: PathBuf,
+    pub sample_rate: u32,
+    pub speed: f32,
+    /// Semitones, drums are never transposed
+    pub transpose: i8,
+    /// Track numbers as listed in the tracks menu, counted from 1
+    pub muted_tracks: Vec<usize>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            soundfont: crate::resources::default_sf2(),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            speed: 1.0,
+            transpose: 0,
+            muted_tracks: Vec::new(),
+        }
+    }
+}
+
+/// Handles `--render-wav`, returns exit code or `None` when the app should start normally
+pub fn run_from_args() -> Option<i32> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) != Some("--render-wav") {
+        return None;
+    }
+
+    {
+        use env_logger::Env;
+        env_logger::Builder::from_env(Env::default().default_filter_or("neothesia=info")).init();
+    }
+
+    let res = parse_args(&args[1..]).and_then(|(out, song, options)| {
+        let midi = lib_midi::Midi::new(song.to_str().unwrap_or_default())?;
+        render(&midi, &out, &options).map_err(|err| err.to_string())?;
+        Ok(out)
+    });
+
+    match res {
+        Ok(out) => {
+            log::info!("Rendered {}", out.display());
+            Some(0)
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", USAGE);
+            Some(1)
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<(PathBuf, PathBuf, RenderOptions), String> {
+    let mut options = RenderOptions::default();
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value of {}", name))
+        };
+        let invalid = |name: &str| format!("Invalid value of {}", name);
+
+        match arg.as_str() {
+            "--speed" => {
+                options.speed = value(arg)?.parse().map_err(|_| invalid(arg))?;
+                if options.speed <= 0.0 {
+                    return Err(invalid(arg));
+                }
+            }
+            "--transpose" => {
+                options.transpose = value(arg)?.parse().map_err(|_| invalid(arg))?;
+            }
+            "--mute" => {
+                options.muted_tracks = value(arg)?
+                    .split(',')
+                    .map(|id| id.trim().parse().map_err(|_| invalid(arg)))
+                    .collect::<Result<_, _>>()?;
+            }
+            "--soundfont" => {
+                options.soundfont = PathBuf::from(value(arg)?);
+            }
+            "--sample-rate" => {
+                options.sample_rate = value(arg)?.parse().map_err(|_| invalid(arg))?;
+                if options.sample_rate == 0 {
+                    return Err(invalid(arg));
+                }
+            }
+            _ => paths.push(PathBuf::from(arg)),
+        }
+    }
+
+    match paths.as_slice() {
+        [out, song] => Ok((out.clone(), song.clone(), options)),
+        _ => Err("Expected output and song paths".into()),
+    }
+}
+
+/// Plays the song through the synth faster than real time and writes it to 16 bit WAV
+pub fn render(
+    midi: &lib_midi::Midi,
+    out: &Path,
+    options: &RenderOptions,
+) -> Result<(), Box<dyn Error>> {
+    let mut renderer = SynthRenderer::new(&options.soundfont, options.sample_rate)?;
+    let (events, end) = song_events(midi, options);
+
+    let total_frames = ((end + TAIL) * options.sample_rate as f32) as u64;
+
+    let mut writer = WavWriter::create(out, options.sample_rate, CHANNELS)?;
+    let mut block = vec![0.0; BLOCK_FRAMES * CHANNELS as usize];
+    let mut events = events.into_iter().peekable();
+
+    let mut frame = 0;
+    while frame < total_frames {
+        let len = (total_frames - frame).min(BLOCK_FRAMES as u64);
+        let block_end = frame + len;
+
+        while let Some(event) = events.next_if(|e| e.frame.unwrap_or(0) < block_end) {
+            renderer.queue(event);
+        }
+
+        let block = &mut block[..len as usize * CHANNELS as usize];
+        renderer.render(block, CHANNELS as usize);
+        writer.write(block)?;
+
+        frame = block_end;
+    }
+
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Song events placed on sample frames, along with the time (in seconds) the song ends
+fn song_events(midi: &lib_midi::Midi, options: &RenderOptions) -> (Vec<SynthEvent>, f32) {
+    let mut track_mixer = TrackMixer::new(Some(midi));
+    for id in options.muted_tracks.iter() {
+        if let Some(track) = id.checked_sub(1).and_then(|id| track_mixer.get_mut(id)) {
+            track.muted = true;
+        }
+    }
+
+    let sample_rate = options.sample_rate as f32;
+    let to_frame = |time: f32| (time.max(0.0) / options.speed * sample_rate) as u64;
+
+    let transpose = |ch: u8, note: u8| {
+        if ch == 9 {
+            note
+        } else {
+            (note as i16 + options.transpose as i16).max(0).min(127) as u8
+        }
+    };
+
+    let merged_track = &midi.merged_track;
+    let mut events = Vec::new();
+    let mut end = 0.0f32;
+
+    for e in merged_track.channel_events.iter() {
+        events.push(SynthEvent {
+            frame: Some(to_frame(e.time)),
+            event: MidiEvent::channel_message(e.ch, &e.message),
+        });
+    }
+
+    let notes = merged_track
+        .notes
+        .iter()
+        .filter(|n| track_mixer.is_audible(n.track_id));
+
+    // Offs go first, so a note that is repeated right away is not cut off
+    let mut ons = Vec::new();
+    // Notes that start and end on the same frame, their off has to follow their own on
+    let mut zero_length = Vec::new();
+    for n in notes {
+        let key = transpose(n.ch, n.note);
+        let note_end = n.start + n.duration;
+        end = end.max(note_end);
+
+        let on_frame = to_frame(n.start);
+        // Zero length note is released on the frame it starts
+        let off_frame = to_frame(note_end).max(on_frame);
+
+        let on = SynthEvent {
+            frame: Some(on_frame),
+            event: MidiEvent::NoteOn {
+                ch: n.ch,
+                key,
+                vel: n.vel,
+            },
+        };
+        let off = SynthEvent {
+            frame: Some(off_frame),
+            event: MidiEvent::NoteOff { ch: n.ch, key },
+        };
+
+        if off_frame == on_frame {
+            zero_length.push(on);
+            zero_length.push(off);
+        } else {
+            events.push(off);
+            ons.push(on);
+        }
+    }
+    events.append(&mut ons);
+    events.append(&mut zero_length);
+
+    // Stable, events of the same frame keep the order above
+    events.sort_by_key(|e| e.frame);
+
+    (events, end / options.speed)
+}
+
+/// Streams interleaved samples to 16 bit PCM WAV file,
+/// sizes in the header are filled in by `finish`
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+}
+
+impl WavWriter {
+    const BITS: u16 = 16;
+
+    pub fn create(path: &Path, sample_rate: u32, channels: u16) -> std::io::Result<Self> {
+        let block_align = channels * Self::BITS / 8;
+        let byte_rate = sample_rate * block_align as u32;
+
+        let mut file = BufWriter::new(File::create(path)?);
+
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&channels.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&byte_rate.to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&Self::BITS.to_le_bytes())?;
+
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self { file, data_len: 0 })
+    }
+
+    pub fn write(&mut self, samples: &[f32]) -> std::io::Result<()> {
+        for sample in samples {
+            let sample = (sample.max(-1.0).min(1.0) * i16::MAX as f32) as i16;
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+        self.data_len += (samples.len() * Self::BITS as usize / 8) as u32;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> std::io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.data_len).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("neothesia-wav-render-{}", name))
+    }
+
+    #[test]
+    fn parse_paths_and_options() {
+        let (out, song, options) = parse_args(&args(&[
+            "out.wav",
+            "--speed",
+            "0.5",
+            "--transpose",
+            "-2",
+            "song.mid",
+            "--mute",
+            "1, 3",
+            "--soundfont",
+            "piano.sf2",
+            "--sample-rate",
+            "48000",
+        ]))
+        .unwrap();
+
+        assert_eq!(out, PathBuf::from("out.wav"));
+        assert_eq!(song, PathBuf::from("song.mid"));
+        assert_eq!(options.speed, 0.5);
+        assert_eq!(options.transpose, -2);
+        assert_eq!(options.muted_tracks, vec![1, 3]);
+        assert_eq!(options.soundfont, PathBuf::from("piano.sf2"));
+        assert_eq!(options.sample_rate, 48000);
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let (_, _, options) = parse_args(&args(&["out.wav", "song.mid"])).unwrap();
+
+        assert_eq!(options.speed, 1.0);
+        assert_eq!(options.transpose, 0);
+        assert!(options.muted_tracks.is_empty());
+        assert_eq!(options.sample_rate, DEFAULT_SAMPLE_RATE);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_values() {
+        let invalid = [
+            &["out.wav", "song.mid", "--speed", "0"][..],
+            &["out.wav", "song.mid", "--speed", "fast"],
+            &["out.wav", "song.mid", "--sample-rate", "0"],
+            &["out.wav", "song.mid", "--transpose", "200"],
+            &["out.wav", "song.mid", "--mute", "1,x"],
+            &["out.wav", "song.mid", "--speed"],
+            &["out.wav"],
+            &["out.wav", "song.mid", "extra.mid"],
+        ];
+
+        for case in invalid.iter() {
+            assert!(parse_args(&args(case)).is_err(), "{:?}", case);
+        }
+    }
+
+    #[test]
+    fn zero_length_note_is_released_after_it_starts() {
+        let events = [
+            lib_midi::RecordedEvent {
+                time: 0.5,
+                ch: 0,
+                message: lib_midi::RecordedMessage::NoteOn { key: 60, vel: 100 },
+            },
+            lib_midi::RecordedEvent {
+                time: 0.5,
+                ch: 0,
+                message: lib_midi::RecordedMessage::NoteOff { key: 60 },
+            },
+        ];
+        let midi = lib_midi::parse_recording(&lib_midi::TempoMap::new(480), &events).unwrap();
+
+        let (events, _) = song_events(&midi, &RenderOptions::default());
+        let notes: Vec<(Option<u64>, bool)> = events
+            .iter()
+            .filter_map(|e| match e.event {
+                MidiEvent::NoteOn { .. } => Some((e.frame, true)),
+                MidiEvent::NoteOff { .. } => Some((e.frame, false)),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].0, notes[1].0);
+        assert!(notes[0].1 && !notes[1].1);
+    }
+
+    #[test]
+    fn wav_header_sizes() {
+        let path = temp_path("header.wav");
+
+        let mut writer = WavWriter::create(&path, 48000, 2).unwrap();
+        writer.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        writer.finish().unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+        let u32_at =
+            |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+        let i16_at = |at: usize| u16_at(at) as i16;
+
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32_at(4), 36 + 8);
+        assert_eq!(&data[8..12], b"WAVE");
+        assert_eq!(u16_at(22), 2);
+        assert_eq!(u32_at(24), 48000);
+        assert_eq!(u32_at(28), 48000 * 4);
+        assert_eq!(u16_at(32), 4);
+        assert_eq!(u16_at(34), 16);
+        assert_eq!(&data[36..40], b"data");
+        assert_eq!(u32_at(40), 8);
+
+        // Samples are clamped to full scale
+        assert_eq!(i16_at(44), 0);
+        assert_eq!(i16_at(46), i16::MAX);
+        assert_eq!(i16_at(48), -i16::MAX);
+        assert_eq!(i16_at(50), i16::MAX);
+    }
+}